@@ -0,0 +1,49 @@
+//! Compares how `i2c_write_block_data` joins the command and data into one
+//! message against the previous implementation, which collected them into a
+//! new `Vec` on every call.
+//!
+//! The joining helper is included from the crate's sources, so no adapter is
+//! needed and the ioctls are left out of the measurement. Run with
+//! `cargo bench`.
+
+#[path = "../src/join.rs"]
+mod join;
+
+use std::{
+    hint::black_box,
+    io, iter,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: u32 = 1_000_000;
+
+// Stands in for the transfer, which only reads the joined message
+fn transfer(data: &[u8]) -> io::Result<()> {
+    black_box(data);
+    Ok(())
+}
+
+fn old_join(command: u8, value: &[u8]) -> io::Result<()> {
+    transfer(&iter::once(command).chain(value.iter().cloned()).collect::<Vec<_>>())
+}
+
+fn bench<F: FnMut() -> io::Result<()>>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f().expect("join");
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let mut scratch = vec![0u8; 0x10000];
+
+    println!("{:>6} {:>12} {:>12} {:>12}", "bytes", "old", "new", "scratch");
+    for &len in &[8usize, 32, 64, 255, 256, 1024, 4096] {
+        let value = vec![0xa5u8; len];
+        let old = bench(|| old_join(black_box(0x10), black_box(&value)));
+        let new = bench(|| join::join_command(black_box(0x10), black_box(&value), None, transfer));
+        let with = bench(|| join::join_command(black_box(0x10), black_box(&value), Some(&mut scratch), transfer));
+        println!("{:>6} {:>12?} {:>12?} {:>12?}", len, old, new, with);
+    }
+}
@@ -0,0 +1,211 @@
+//! SMBALERT# handling through the SMBus Alert Response Address.
+//!
+//! When a device asserts SMBALERT#, the host reads a byte from the
+//! [ALERT_RESPONSE_ADDRESS] and the alerting device with the lowest address
+//! responds with its own address, releasing the alert line.
+//!
+//! The kernel does not expose SMBALERT# to userspace. Adapters that route it
+//! to an interrupt bind the `smbus_alert` driver instead, which claims the
+//! Alert Response Address and services alerts itself; use
+//! [kernel_handles_alerts] to detect this. Otherwise the signal must be
+//! monitored through whatever the board wires it to, typically a GPIO, with
+//! [AlertLine].
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{alert::{AlertDispatcher, AlertLine}, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut i2c = I2c::from_path("/dev/i2c-0")?;
+//! let mut line = AlertLine::open("/sys/class/gpio/gpio42/value")?;
+//! let mut dispatcher = AlertDispatcher::new();
+//! dispatcher.register(0x48, |alert| println!("temperature alert from {:#04x}", alert.address));
+//! loop {
+//!     line.wait(None)?;
+//!     dispatcher.process(&mut i2c)?;
+//! }
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{is_nack, I2c},
+    std::{
+        collections::HashMap,
+        fs::{self, File},
+        io::{self, Read, Seek, SeekFrom},
+        os::unix::io::{AsRawFd, RawFd},
+        path::Path,
+        time::Duration,
+    },
+};
+
+/// The SMBus Alert Response Address.
+pub const ALERT_RESPONSE_ADDRESS: u16 = 0x0c;
+
+// Bounds the number of alerts serviced at once, in case a device never
+// releases SMBALERT#
+const MAX_ALERTS: usize = 0x80;
+
+/// A response from an alerting device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Alert {
+    /// The slave address of the device that raised the alert.
+    pub address: u16,
+    /// The device specific flag in the low bit of the response.
+    pub flag: bool,
+}
+
+/// Reads the Alert Response Address to identify an alerting device.
+///
+/// Returns `None` if no device responded. The slave address of `i2c` is left
+/// pointing at the Alert Response Address.
+pub fn read_alert<I: AsRawFd>(i2c: &mut I2c<I>) -> io::Result<Option<Alert>> {
+    i2c.smbus_set_slave_address(ALERT_RESPONSE_ADDRESS, false)?;
+    match i2c.smbus_read_byte() {
+        Ok(status) => Ok(Some(Alert {
+            address: (status >> 1) as u16,
+            flag: status & 1 != 0,
+        })),
+        Err(ref e) if is_nack(e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether the kernel `smbus_alert` driver is bound to adapter `bus`.
+///
+/// The driver claims the Alert Response Address, so [read_alert] fails with
+/// `EBUSY` and alerts are only delivered to kernel drivers.
+pub fn kernel_handles_alerts(bus: u32) -> io::Result<bool> {
+    let path = format!("/sys/bus/i2c/devices/{}-{:04x}/name", bus, ALERT_RESPONSE_ADDRESS);
+    match fs::read_to_string(path) {
+        Ok(name) => Ok(name.trim_end() == "smbus_alert"),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+type AlertHandler<'a> = Box<dyn FnMut(&Alert) + 'a>;
+
+/// Dispatches SMBus alerts to per-address callbacks.
+#[derive(Default)]
+pub struct AlertDispatcher<'a> {
+    handlers: HashMap<u16, AlertHandler<'a>>,
+    fallback: Option<AlertHandler<'a>>,
+}
+
+impl<'a> AlertDispatcher<'a> {
+    /// Creates a dispatcher with no registered callbacks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a callback for alerts raised by the device at `address`,
+    /// replacing any previous callback for that address.
+    pub fn register<F: FnMut(&Alert) + 'a>(&mut self, address: u16, f: F) {
+        self.handlers.insert(address, Box::new(f));
+    }
+
+    /// Removes the callback for `address`.
+    pub fn unregister(&mut self, address: u16) {
+        self.handlers.remove(&address);
+    }
+
+    /// Sets a callback for alerts from devices without a registered callback.
+    pub fn set_fallback<F: FnMut(&Alert) + 'a>(&mut self, f: F) {
+        self.fallback = Some(Box::new(f));
+    }
+
+    /// Invokes the callback registered for the alert's address.
+    ///
+    /// Returns `false` if no callback handled the alert.
+    pub fn dispatch(&mut self, alert: &Alert) -> bool {
+        match self.handlers.get_mut(&alert.address).or(self.fallback.as_mut()) {
+            Some(handler) => {
+                handler(alert);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Reads and dispatches alerts until no device responds.
+    ///
+    /// Processing stops early if the same device responds twice in a row,
+    /// as it has failed to release SMBALERT#. Returns the number of alerts
+    /// dispatched.
+    pub fn process<I: AsRawFd>(&mut self, i2c: &mut I2c<I>) -> io::Result<usize> {
+        let mut previous = None;
+        let mut count = 0;
+        while count < MAX_ALERTS {
+            let alert = match read_alert(i2c)? {
+                Some(alert) => alert,
+                None => break,
+            };
+            if previous == Some(alert.address) {
+                break
+            }
+            previous = Some(alert.address);
+
+            self.dispatch(&alert);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+/// An SMBALERT# signal exposed through a pollable sysfs attribute, such as a
+/// GPIO `value` file configured with an `edge` trigger.
+///
+/// This is a plain sysfs attribute reader: locating and configuring the
+/// attribute is left to the caller, as the wiring of SMBALERT# is board
+/// specific. It cannot observe alerts on adapters where
+/// [kernel_handles_alerts] is true.
+pub struct AlertLine {
+    file: File,
+}
+
+impl AlertLine {
+    /// Opens the sysfs attribute at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        File::open(path).map(|file| AlertLine { file })
+    }
+
+    /// Whether SMBALERT# is currently asserted. The signal is active low.
+    pub fn is_asserted(&mut self) -> io::Result<bool> {
+        let mut value = [0u8; 8];
+        self.file.seek(SeekFrom::Start(0))?;
+        let len = self.file.read(&mut value)?;
+        Ok(value[..len].first() == Some(&b'0'))
+    }
+
+    /// Waits until SMBALERT# is asserted, or `timeout` elapses.
+    ///
+    /// Returns `false` on timeout.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        // Reading the attribute re-arms the notification
+        if self.is_asserted()? {
+            return Ok(true)
+        }
+
+        let mut fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLPRI | libc::POLLERR,
+            revents: 0,
+        };
+        let timeout = timeout.map(|t| t.as_millis().min(libc::c_int::MAX as u128) as libc::c_int);
+        match unsafe { libc::poll(&mut fd, 1, timeout.unwrap_or(-1)) } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Ok(false),
+            _ => self.is_asserted(),
+        }
+    }
+}
+
+impl AsRawFd for AlertLine {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
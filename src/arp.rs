@@ -0,0 +1,369 @@
+//! SMBus 2.0 Address Resolution Protocol.
+//!
+//! ARP-capable devices respond at the [SMBUS_DEVICE_DEFAULT_ADDRESS] until
+//! they are assigned an address, identifying themselves with a unique device
+//! identifier ([Udid]).
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{arp::{AddressPool, Arp}, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut arp = Arp::new(I2c::from_path("/dev/i2c-0")?)?;
+//! let mut pool = AddressPool::new();
+//! for device in arp.assign_addresses(&mut pool)? {
+//!     println!("{:04x}:{:04x} at {:#04x}", device.udid.vendor_id, device.udid.device_id, device.address);
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{is_nack, I2c},
+    std::{io, os::unix::io::AsRawFd},
+};
+
+/// The address ARP-capable devices respond to before being assigned one.
+pub const SMBUS_DEVICE_DEFAULT_ADDRESS: u16 = 0x61;
+
+const PREPARE_TO_ARP: u8 = 0x01;
+const RESET_DEVICE: u8 = 0x02;
+const GET_UDID: u8 = 0x03;
+const ASSIGN_ADDRESS: u8 = 0x04;
+const UDID_LEN: usize = 16;
+const NO_ADDRESS: u8 = 0xff;
+// Every possible address can only be assigned once
+const MAX_DEVICES: usize = 0x80;
+
+/// How a device's slave address is determined.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    /// The device has a fixed address that must be assigned back to it.
+    Fixed,
+    /// The assigned address is retained across power cycles.
+    DynamicPersistent,
+    /// The assigned address is lost on power loss.
+    DynamicVolatile,
+    /// The device uses a random number in place of a unique device ID.
+    RandomNumber,
+}
+
+/// An SMBus unique device identifier.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Udid {
+    /// How the device's address is determined.
+    pub address_type: AddressType,
+    /// The device supports Packet Error Checking.
+    pub pec_supported: bool,
+    /// UDID version.
+    pub version: u8,
+    /// Silicon revision.
+    pub revision: u8,
+    /// Device manufacturer's ID, as assigned by the SBS Implementers Forum or PCI SIG.
+    pub vendor_id: u16,
+    /// Device ID assigned by the manufacturer.
+    pub device_id: u16,
+    /// Supported SMBus version and protocol features.
+    pub interface: u16,
+    /// Subsystem vendor ID, or zero.
+    pub subsystem_vendor_id: u16,
+    /// Subsystem device ID, or zero.
+    pub subsystem_device_id: u16,
+    /// Vendor specific unique ID.
+    pub vendor_specific_id: u32,
+}
+
+impl Udid {
+    /// Parses a UDID as transmitted on the bus.
+    ///
+    /// ```rust
+    /// use i2c_linux::arp::{AddressType, Udid};
+    ///
+    /// let bytes = [0x81, 0x08, 0x80, 0x86, 0x12, 0x34, 0x00, 0x04, 0, 0, 0, 0, 0xde, 0xad, 0xbe, 0xef];
+    /// let udid = Udid::from_bytes(&bytes);
+    /// assert_eq!(udid.address_type, AddressType::DynamicVolatile);
+    /// assert!(udid.pec_supported);
+    /// assert_eq!(udid.version, 1);
+    /// assert_eq!(udid.vendor_id, 0x8086);
+    /// assert_eq!(udid.device_id, 0x1234);
+    /// assert_eq!(udid.vendor_specific_id, 0xdeadbeef);
+    /// assert_eq!(udid.to_bytes(), bytes);
+    /// ```
+    pub fn from_bytes(bytes: &[u8; UDID_LEN]) -> Self {
+        let word = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        Udid {
+            address_type: match bytes[0] >> 6 {
+                0b00 => AddressType::Fixed,
+                0b01 => AddressType::DynamicPersistent,
+                0b10 => AddressType::DynamicVolatile,
+                _ => AddressType::RandomNumber,
+            },
+            pec_supported: bytes[0] & 0x01 != 0,
+            version: (bytes[1] >> 3) & 0x07,
+            revision: bytes[1] & 0x07,
+            vendor_id: word(2),
+            device_id: word(4),
+            interface: word(6),
+            subsystem_vendor_id: word(8),
+            subsystem_device_id: word(10),
+            vendor_specific_id: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        }
+    }
+
+    /// Serializes the UDID in bus order.
+    pub fn to_bytes(&self) -> [u8; UDID_LEN] {
+        let address_type = match self.address_type {
+            AddressType::Fixed => 0b00,
+            AddressType::DynamicPersistent => 0b01,
+            AddressType::DynamicVolatile => 0b10,
+            AddressType::RandomNumber => 0b11,
+        };
+
+        let mut bytes = [0u8; UDID_LEN];
+        bytes[0] = (address_type << 6) | self.pec_supported as u8;
+        bytes[1] = ((self.version & 0x07) << 3) | (self.revision & 0x07);
+        bytes[2..4].copy_from_slice(&self.vendor_id.to_be_bytes());
+        bytes[4..6].copy_from_slice(&self.device_id.to_be_bytes());
+        bytes[6..8].copy_from_slice(&self.interface.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.subsystem_vendor_id.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.subsystem_device_id.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.vendor_specific_id.to_be_bytes());
+        bytes
+    }
+}
+
+/// A device's response to a Get UDID command.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UdidResponse {
+    /// The device's unique identifier.
+    pub udid: Udid,
+    /// The device's current slave address, if it has one.
+    pub address: Option<u16>,
+}
+
+/// A device that was assigned an address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ArpDevice {
+    /// The device's unique identifier.
+    pub udid: Udid,
+    /// The slave address assigned to the device.
+    pub address: u16,
+}
+
+/// Tracks which slave addresses are free to be assigned to ARP devices.
+#[derive(Debug, Clone)]
+pub struct AddressPool {
+    used: [bool; 0x80],
+}
+
+impl Default for AddressPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AddressPool {
+    /// Addresses reserved by the I2C and SMBus specifications.
+    pub const RESERVED: &'static [u16] = &[
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, // general call, CBUS, Hs-mode
+        0x08, // SMBus host
+        0x09, 0x0a, 0x0b, // smart battery charger, selector and battery
+        0x0c, // SMBus Alert Response Address
+        0x28, // ACCESS.bus host
+        0x2c, 0x2d, // reserved by previous SMBus versions
+        0x37, // ACCESS.bus default
+        0x48, 0x49, 0x4a, 0x4b, // prototypes
+        0x61, // SMBus device default address
+        0x78, 0x79, 0x7a, 0x7b, 0x7c, 0x7d, 0x7e, 0x7f, // 10-bit addressing, reserved
+    ];
+
+    /// Creates a pool of all non-reserved addresses.
+    pub fn new() -> Self {
+        let mut pool = AddressPool { used: [false; 0x80] };
+        for &address in Self::RESERVED {
+            pool.reserve(address);
+        }
+        pool
+    }
+
+    /// Marks an address as used, such as for fixed address devices known to
+    /// be on the bus.
+    ///
+    /// Returns `false` if the address was already in use.
+    pub fn reserve(&mut self, address: u16) -> bool {
+        match self.used.get_mut(address as usize) {
+            Some(used) if !*used => {
+                *used = true;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns an address to the pool.
+    pub fn release(&mut self, address: u16) {
+        if let Some(used) = self.used.get_mut(address as usize) {
+            *used = false;
+        }
+    }
+
+    /// Whether the address is free to be assigned.
+    pub fn is_available(&self, address: u16) -> bool {
+        self.used.get(address as usize).map(|&used| !used).unwrap_or(false)
+    }
+
+    /// Takes the lowest free address from the pool.
+    ///
+    /// ```rust
+    /// use i2c_linux::arp::AddressPool;
+    ///
+    /// let mut pool = AddressPool::new();
+    /// assert_eq!(pool.allocate(), Some(0x0d));
+    /// assert!(pool.reserve(0x0e));
+    /// assert_eq!(pool.allocate(), Some(0x0f));
+    /// assert!(!pool.is_available(0x0b));
+    /// assert!(!pool.is_available(0x2c));
+    /// assert!(!pool.is_available(0x61));
+    /// ```
+    pub fn allocate(&mut self) -> Option<u16> {
+        let address = self.used.iter().position(|&used| !used)? as u16;
+        self.reserve(address);
+        Some(address)
+    }
+}
+
+/// An ARP master.
+pub struct Arp<I> {
+    inner: I2c<I>,
+}
+
+impl<I> Arp<I> {
+    /// Consumes the handle to return the underlying I2C bus.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying I2C bus.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying I2C bus.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
+
+impl<I: AsRawFd> Arp<I> {
+    /// Creates an ARP master, addressing the SMBus device default address
+    /// with Packet Error Checking enabled.
+    pub fn new(mut i2c: I2c<I>) -> io::Result<Self> {
+        i2c.smbus_set_slave_address(SMBUS_DEVICE_DEFAULT_ADDRESS, false)?;
+        i2c.smbus_set_pec(true)?;
+
+        Ok(Arp { inner: i2c })
+    }
+
+    /// Clears the Address Resolved flag of all ARP-capable devices, so that
+    /// they respond to the general Get UDID command.
+    pub fn prepare_to_arp(&mut self) -> io::Result<()> {
+        self.inner.smbus_write_byte(PREPARE_TO_ARP)
+    }
+
+    /// Resets all ARP-capable devices, clearing volatile address assignments.
+    pub fn reset_device(&mut self) -> io::Result<()> {
+        self.inner.smbus_write_byte(RESET_DEVICE)
+    }
+
+    /// Resets the ARP state of the device at `address`.
+    pub fn reset_device_directed(&mut self, address: u16) -> io::Result<()> {
+        self.inner.smbus_write_byte(directed_command(address, false)?)
+    }
+
+    /// Retrieves the UDID of an unresolved device.
+    ///
+    /// Returns `None` if no devices responded. When multiple devices are
+    /// unresolved, the one with the lowest UDID wins bus arbitration.
+    pub fn get_udid(&mut self) -> io::Result<Option<UdidResponse>> {
+        match self.read_udid(GET_UDID) {
+            Err(ref e) if is_nack(e) => Ok(None),
+            res => res.map(Some),
+        }
+    }
+
+    /// Retrieves the UDID of the device at `address`.
+    pub fn get_udid_directed(&mut self, address: u16) -> io::Result<UdidResponse> {
+        self.read_udid(directed_command(address, true)?)
+    }
+
+    /// Assigns an address to the device identified by `udid`.
+    pub fn assign_address(&mut self, udid: &Udid, address: u16) -> io::Result<()> {
+        let mut data = [0u8; UDID_LEN + 1];
+        data[..UDID_LEN].copy_from_slice(&udid.to_bytes());
+        data[UDID_LEN] = address_byte(address)?;
+        self.inner.smbus_write_block_data(ASSIGN_ADDRESS, &data)
+    }
+
+    /// Performs a full address resolution cycle, assigning addresses from
+    /// `pool` to every unresolved device.
+    ///
+    /// Devices with fixed addresses, or persistent addresses still available
+    /// in the pool, are assigned their current address.
+    pub fn assign_addresses(&mut self, pool: &mut AddressPool) -> io::Result<Vec<ArpDevice>> {
+        self.prepare_to_arp()?;
+
+        let mut devices = Vec::new();
+        while let Some(UdidResponse { udid, address }) = self.get_udid()? {
+            if devices.len() >= MAX_DEVICES {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "SMBus ARP did not converge"))
+            }
+
+            let address = match (udid.address_type, address) {
+                (AddressType::Fixed, Some(address)) => {
+                    pool.reserve(address);
+                    address
+                },
+                (AddressType::DynamicPersistent, Some(address)) if pool.reserve(address) => address,
+                _ => pool.allocate().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::AddrNotAvailable, "SMBus ARP address pool exhausted")
+                })?,
+            };
+            self.assign_address(&udid, address)?;
+            devices.push(ArpDevice { udid, address });
+        }
+
+        Ok(devices)
+    }
+
+    fn read_udid(&mut self, command: u8) -> io::Result<UdidResponse> {
+        let mut data = [0u8; UDID_LEN + 1];
+        let len = self.inner.smbus_read_block_data(command, &mut data)?;
+        if len != data.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid SMBus UDID length"))
+        }
+
+        let mut udid = [0u8; UDID_LEN];
+        udid.copy_from_slice(&data[..UDID_LEN]);
+        Ok(UdidResponse {
+            udid: Udid::from_bytes(&udid),
+            address: match data[UDID_LEN] {
+                NO_ADDRESS => None,
+                address => Some((address >> 1) as u16),
+            },
+        })
+    }
+}
+
+fn address_byte(address: u16) -> io::Result<u8> {
+    if address < 0x80 {
+        Ok(((address as u8) << 1) | 1)
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid SMBus ARP address"))
+    }
+}
+
+fn directed_command(address: u16, get_udid: bool) -> io::Result<u8> {
+    address_byte(address).map(|byte| (byte & !1) | get_udid as u8)
+}
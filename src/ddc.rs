@@ -0,0 +1,285 @@
+//! Display Data Channel EDID access and parsing.
+//!
+//! Graphics adapters expose the DDC lines of each display connector as an I2C
+//! bus. The monitor's EDID is available at [EDID_ADDRESS], with the E-DDC
+//! segment pointer at [SEGMENT_ADDRESS] used to reach extension blocks past the
+//! first 256 bytes.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{ddc, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut i2c = I2c::from_path("/dev/i2c-4")?;
+//! let data = ddc::read_edid(&mut i2c)?;
+//! let edid = ddc::Edid::parse(&data)?;
+//! println!("{} {:?}", edid.manufacturer, edid.monitor_name);
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{I2c, Message, ReadFlags, WriteFlags},
+    std::{io, os::unix::io::AsRawFd},
+};
+
+pub mod ci;
+
+/// The slave address of the EDID EEPROM.
+pub const EDID_ADDRESS: u16 = 0x50;
+
+/// The slave address of the E-DDC segment pointer.
+pub const SEGMENT_ADDRESS: u16 = 0x30;
+
+/// Size of a single EDID block.
+pub const EDID_BLOCK_SIZE: usize = 128;
+
+const EDID_HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const DESCRIPTOR_OFFSETS: [usize; 4] = [54, 72, 90, 108];
+const DESCRIPTOR_SERIAL: u8 = 0xff;
+const DESCRIPTOR_NAME: u8 = 0xfc;
+
+/// Reads a single 128 byte EDID block.
+///
+/// Blocks past the first two are addressed through the E-DDC segment pointer.
+pub fn read_edid_block<I: AsRawFd>(i2c: &mut I2c<I>, block: u8, data: &mut [u8; EDID_BLOCK_SIZE]) -> io::Result<()> {
+    let segment = [block / 2];
+    let offset = [(block % 2) * EDID_BLOCK_SIZE as u8];
+    let mut msgs = [
+        Message::Write {
+            address: SEGMENT_ADDRESS,
+            data: &segment,
+            flags: WriteFlags::default(),
+        },
+        Message::Write {
+            address: EDID_ADDRESS,
+            data: &offset,
+            flags: WriteFlags::default(),
+        },
+        Message::Read {
+            address: EDID_ADDRESS,
+            data: &mut data[..],
+            flags: ReadFlags::default(),
+        },
+    ];
+    // Many monitors without E-DDC support will NACK the segment pointer
+    let msgs = if segment[0] == 0 { &mut msgs[1..] } else { &mut msgs[..] };
+    i2c.i2c_transfer(msgs)?;
+
+    if msgs[msgs.len() - 1].len() < EDID_BLOCK_SIZE {
+        Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short EDID read"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads the base EDID block and all of its extension blocks.
+///
+/// Each block's checksum is validated before it is returned.
+pub fn read_edid<I: AsRawFd>(i2c: &mut I2c<I>) -> io::Result<Vec<u8>> {
+    let mut block = [0u8; EDID_BLOCK_SIZE];
+    read_edid_block(i2c, 0, &mut block)?;
+    validate_block(&block)?;
+    if block[..8] != EDID_HEADER {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid EDID header"))
+    }
+
+    let extensions = block[126];
+    let mut data = Vec::with_capacity(EDID_BLOCK_SIZE * (extensions as usize + 1));
+    data.extend_from_slice(&block);
+    for index in 1..=extensions {
+        read_edid_block(i2c, index, &mut block)?;
+        validate_block(&block)?;
+        data.extend_from_slice(&block);
+    }
+
+    Ok(data)
+}
+
+/// Checks whether all bytes of an EDID block sum to zero.
+pub fn checksum_valid(block: &[u8]) -> bool {
+    block.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+fn validate_block(block: &[u8]) -> io::Result<()> {
+    if checksum_valid(block) {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "EDID checksum mismatch"))
+    }
+}
+
+/// A detailed timing descriptor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DetailedTiming {
+    /// Pixel clock in kHz.
+    pub pixel_clock_khz: u32,
+    /// Horizontal addressable pixels.
+    pub horizontal_active: u16,
+    /// Horizontal blanking pixels.
+    pub horizontal_blanking: u16,
+    /// Horizontal front porch in pixels.
+    pub horizontal_sync_offset: u16,
+    /// Horizontal sync pulse width in pixels.
+    pub horizontal_sync_width: u16,
+    /// Vertical addressable lines.
+    pub vertical_active: u16,
+    /// Vertical blanking lines.
+    pub vertical_blanking: u16,
+    /// Vertical front porch in lines.
+    pub vertical_sync_offset: u16,
+    /// Vertical sync pulse width in lines.
+    pub vertical_sync_width: u16,
+    /// Horizontal image size in millimetres.
+    pub horizontal_size_mm: u16,
+    /// Vertical image size in millimetres.
+    pub vertical_size_mm: u16,
+    /// Whether the mode is interlaced.
+    pub interlaced: bool,
+}
+
+impl DetailedTiming {
+    /// Parses an 18 byte detailed timing descriptor.
+    ///
+    /// Returns `None` if the descriptor is a display descriptor instead.
+    pub fn parse(d: &[u8]) -> Option<Self> {
+        if d.len() < 18 {
+            return None
+        }
+
+        let pixel_clock = u16::from_le_bytes([d[0], d[1]]);
+        if pixel_clock == 0 {
+            return None
+        }
+
+        let hi = |byte: u8, shift: u8, mask: u8| (((byte >> shift) & mask) as u16) << 8;
+        Some(DetailedTiming {
+            pixel_clock_khz: pixel_clock as u32 * 10,
+            horizontal_active: d[2] as u16 | hi(d[4], 4, 0x0f),
+            horizontal_blanking: d[3] as u16 | hi(d[4], 0, 0x0f),
+            vertical_active: d[5] as u16 | hi(d[7], 4, 0x0f),
+            vertical_blanking: d[6] as u16 | hi(d[7], 0, 0x0f),
+            horizontal_sync_offset: d[8] as u16 | hi(d[11], 6, 0x03),
+            horizontal_sync_width: d[9] as u16 | hi(d[11], 4, 0x03),
+            vertical_sync_offset: (d[10] >> 4) as u16 | (((d[11] >> 2) & 0x03) as u16) << 4,
+            vertical_sync_width: (d[10] & 0x0f) as u16 | ((d[11] & 0x03) as u16) << 4,
+            horizontal_size_mm: d[12] as u16 | hi(d[14], 4, 0x0f),
+            vertical_size_mm: d[13] as u16 | hi(d[14], 0, 0x0f),
+            interlaced: d[17] & 0x80 != 0,
+        })
+    }
+
+    /// The vertical refresh rate in millihertz.
+    pub fn refresh_rate_mhz(&self) -> u32 {
+        let htotal = (self.horizontal_active + self.horizontal_blanking) as u64;
+        let vtotal = (self.vertical_active + self.vertical_blanking) as u64;
+        match htotal * vtotal {
+            0 => 0,
+            total => (self.pixel_clock_khz as u64 * 1_000_000 / total) as u32,
+        }
+    }
+}
+
+/// Identifying information parsed from an EDID base block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    /// Three letter PNP manufacturer ID.
+    pub manufacturer: String,
+    /// Manufacturer product code.
+    pub product_code: u16,
+    /// Numeric serial number, or zero if unused.
+    pub serial: u32,
+    /// Week of manufacture, or zero if unspecified.
+    pub week: u8,
+    /// Year of manufacture, or model year if `week` is `0xff`.
+    pub year: u16,
+    /// EDID structure version and revision.
+    pub version: (u8, u8),
+    /// Number of extension blocks following the base block.
+    pub extensions: u8,
+    /// The preferred timing mode, from the first detailed timing descriptor.
+    pub preferred_timing: Option<DetailedTiming>,
+    /// The monitor name display descriptor.
+    pub monitor_name: Option<String>,
+    /// The serial number display descriptor.
+    pub serial_string: Option<String>,
+}
+
+impl Edid {
+    /// Parses the EDID base block. Extension blocks are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use i2c_linux::ddc::{self, Edid};
+    ///
+    /// // Synthetic base block modelled on a 1080p monitor, with unused fields zeroed
+    /// let mut data = [0u8; 128];
+    /// data[..20].copy_from_slice(&[
+    ///     0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x10, 0xac, 0x6d, 0xa0, 0x4c, 0x39, 0x31, 0x30, 0x0c, 0x1b,
+    ///     0x01, 0x04,
+    /// ]);
+    /// data[54..72].copy_from_slice(&[
+    ///     0x02, 0x3a, 0x80, 0x18, 0x71, 0x38, 0x2d, 0x40, 0x58, 0x2c, 0x45, 0x00, 0x13, 0x2b, 0x21, 0x00, 0x00, 0x1e,
+    /// ]);
+    /// data[72..90].copy_from_slice(b"\0\0\0\xff\0CFV9N7CV010L\n");
+    /// data[90..108].copy_from_slice(b"\0\0\0\xfc\0DELL U2417H\n ");
+    /// data[127] = 0u8.wrapping_sub(data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)));
+    /// assert!(ddc::checksum_valid(&data));
+    ///
+    /// let edid = Edid::parse(&data).unwrap();
+    /// assert_eq!(edid.manufacturer, "DEL");
+    /// assert_eq!(edid.product_code, 0xa06d);
+    /// assert_eq!(edid.year, 2017);
+    /// assert_eq!(edid.monitor_name.as_ref().unwrap(), "DELL U2417H");
+    /// assert_eq!(edid.serial_string.as_ref().unwrap(), "CFV9N7CV010L");
+    /// let timing = edid.preferred_timing.unwrap();
+    /// assert_eq!((timing.horizontal_active, timing.vertical_active), (1920, 1080));
+    /// assert_eq!(timing.pixel_clock_khz, 148_500);
+    /// assert_eq!(timing.refresh_rate_mhz(), 60_000);
+    /// ```
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < EDID_BLOCK_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "EDID data truncated"))
+        }
+        let data = &data[..EDID_BLOCK_SIZE];
+        if data[..8] != EDID_HEADER {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid EDID header"))
+        }
+        validate_block(data)?;
+
+        let id = u16::from_be_bytes([data[8], data[9]]);
+        let manufacturer = [10, 5, 0]
+            .iter()
+            .map(|&shift| (b'A' - 1 + ((id >> shift) & 0x1f) as u8) as char)
+            .collect();
+
+        let descriptors = DESCRIPTOR_OFFSETS.iter().map(|&offset| &data[offset..offset + 18]);
+        let descriptor_text = |tag| {
+            descriptors
+                .clone()
+                .find(|d| d[..3] == [0, 0, 0] && d[3] == tag)
+                .map(|d| descriptor_string(&d[5..]))
+        };
+
+        Ok(Edid {
+            manufacturer,
+            product_code: u16::from_le_bytes([data[10], data[11]]),
+            serial: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+            week: data[16],
+            year: 1990 + data[17] as u16,
+            version: (data[18], data[19]),
+            extensions: data[126],
+            preferred_timing: DetailedTiming::parse(&data[DESCRIPTOR_OFFSETS[0]..]),
+            monitor_name: descriptor_text(DESCRIPTOR_NAME),
+            serial_string: descriptor_text(DESCRIPTOR_SERIAL),
+        })
+    }
+}
+
+fn descriptor_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == b'\n').unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim_end().into()
+}
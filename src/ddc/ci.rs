@@ -0,0 +1,259 @@
+//! VESA DDC/CI monitor control, as used by MCCS.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{ddc::ci::{vcp, DdcCi}, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut ddc = DdcCi::new(I2c::from_path("/dev/i2c-4")?);
+//! let brightness = ddc.get_vcp_feature(vcp::BRIGHTNESS)?;
+//! ddc.set_vcp_feature(vcp::BRIGHTNESS, brightness.maximum / 2)?;
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{I2c, Message, ReadFlags, WriteFlags},
+    std::{
+        io,
+        os::unix::io::AsRawFd,
+        thread::sleep,
+        time::{Duration, Instant},
+    },
+};
+
+/// The slave address of a display's DDC/CI interface.
+pub const DDC_CI_ADDRESS: u16 = 0x37;
+
+/// Common MCCS VCP feature codes.
+pub mod vcp {
+    /// Restore factory defaults.
+    pub const RESTORE_FACTORY_DEFAULTS: u8 = 0x04;
+    /// Luminance of the display.
+    pub const BRIGHTNESS: u8 = 0x10;
+    /// Contrast of the display.
+    pub const CONTRAST: u8 = 0x12;
+    /// Active input source.
+    pub const INPUT_SOURCE: u8 = 0x60;
+    /// Audio speaker volume.
+    pub const AUDIO_VOLUME: u8 = 0x62;
+    /// Display power mode.
+    pub const POWER_MODE: u8 = 0xd6;
+    /// Supported MCCS version.
+    pub const VERSION: u8 = 0xdf;
+}
+
+const HOST_ADDRESS: u8 = 0x51;
+const DISPLAY_WRITE_ADDRESS: u8 = (DDC_CI_ADDRESS as u8) << 1;
+const HOST_READ_ADDRESS: u8 = 0x50;
+const LENGTH_FLAG: u8 = 0x80;
+const MAX_PAYLOAD: usize = 32 + 3;
+const MAX_CAPABILITIES_LEN: usize = 0x10000;
+
+const OP_GET_VCP: u8 = 0x01;
+const OP_GET_VCP_REPLY: u8 = 0x02;
+const OP_SET_VCP: u8 = 0x03;
+const OP_SAVE_SETTINGS: u8 = 0x0c;
+const OP_CAPABILITIES: u8 = 0xf3;
+const OP_CAPABILITIES_REPLY: u8 = 0xe3;
+
+const DELAY_REPLY: Duration = Duration::from_millis(40);
+const DELAY_COMMAND: Duration = Duration::from_millis(50);
+const DELAY_SAVE: Duration = Duration::from_millis(200);
+
+/// Computes the XOR checksum of a DDC/CI message.
+///
+/// `address` is the 8-bit destination address for host writes (`0x6e`), or
+/// the virtual host address for display replies (`0x50`).
+pub fn checksum(address: u8, data: &[u8]) -> u8 {
+    data.iter().fold(address, |sum, &b| sum ^ b)
+}
+
+/// The current and maximum values of a VCP feature.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct VcpValue {
+    /// VCP type code: `0` for set parameter, `1` for momentary.
+    pub ty: u8,
+    /// The maximum value of a continuous feature.
+    pub maximum: u16,
+    /// The current value of the feature.
+    pub value: u16,
+}
+
+/// A DDC/CI connection to a display.
+///
+/// Required inter-command delays are enforced automatically, and NULL or
+/// corrupted replies are retried.
+pub struct DdcCi<I> {
+    inner: I2c<I>,
+    ready_at: Option<Instant>,
+    retries: usize,
+}
+
+impl<I> DdcCi<I> {
+    /// Creates a new DDC/CI handle over the display's DDC bus.
+    pub fn new(i2c: I2c<I>) -> Self {
+        DdcCi {
+            inner: i2c,
+            ready_at: None,
+            retries: 3,
+        }
+    }
+
+    /// Sets the number of times to retry a command after a NULL or invalid
+    /// reply.
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+    }
+
+    /// Consumes the handle to return the underlying I2C bus.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying I2C bus.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying I2C bus.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
+
+impl<I: AsRawFd> DdcCi<I> {
+    /// Reads the current value of a VCP feature.
+    pub fn get_vcp_feature(&mut self, code: u8) -> io::Result<VcpValue> {
+        let mut reply = [0u8; MAX_PAYLOAD];
+        self.retry(|ddc| {
+            let len = ddc.request(&[OP_GET_VCP, code], DELAY_REPLY, &mut reply)?;
+            match reply[..len] {
+                [OP_GET_VCP_REPLY, 0, reply_code, ty, max_hi, max_lo, hi, lo] if reply_code == code => Ok(VcpValue {
+                    ty,
+                    maximum: u16::from_be_bytes([max_hi, max_lo]),
+                    value: u16::from_be_bytes([hi, lo]),
+                }),
+                [OP_GET_VCP_REPLY, 1, ..] => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("VCP feature {:#04x} unsupported", code),
+                )),
+                _ => Err(invalid_reply()),
+            }
+        })
+    }
+
+    /// Sets the value of a VCP feature.
+    pub fn set_vcp_feature(&mut self, code: u8, value: u16) -> io::Result<()> {
+        let value = value.to_be_bytes();
+        self.command(&[OP_SET_VCP, code, value[0], value[1]], DELAY_COMMAND)
+    }
+
+    /// Asks the display to persist its current settings.
+    pub fn save_current_settings(&mut self) -> io::Result<()> {
+        self.command(&[OP_SAVE_SETTINGS], DELAY_SAVE)
+    }
+
+    /// Retrieves the display's MCCS capabilities string, reassembled from
+    /// each reply fragment.
+    pub fn capabilities(&mut self) -> io::Result<String> {
+        let mut caps = Vec::new();
+        loop {
+            let offset = caps.len() as u16;
+            let mut reply = [0u8; MAX_PAYLOAD];
+            let fragment = self.retry(|ddc| {
+                let [hi, lo] = offset.to_be_bytes();
+                let len = ddc.request(&[OP_CAPABILITIES, hi, lo], DELAY_COMMAND, &mut reply)?;
+                match reply[..len] {
+                    [OP_CAPABILITIES_REPLY, reply_hi, reply_lo, ..] if [reply_hi, reply_lo] == [hi, lo] => Ok(len),
+                    _ => Err(invalid_reply()),
+                }
+            })?;
+            if fragment <= 3 {
+                break
+            }
+            caps.extend_from_slice(&reply[3..fragment]);
+            if caps.len() > MAX_CAPABILITIES_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "DDC/CI capabilities too long",
+                ))
+            }
+        }
+
+        let end = caps.iter().position(|&b| b == 0).unwrap_or(caps.len());
+        Ok(String::from_utf8_lossy(&caps[..end]).into())
+    }
+
+    fn retry<R, F: FnMut(&mut Self) -> io::Result<R>>(&mut self, mut f: F) -> io::Result<R> {
+        let mut attempt = 0;
+        loop {
+            match f(self) {
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData && attempt < self.retries => attempt += 1,
+                res => return res,
+            }
+        }
+    }
+
+    fn wait(&mut self) {
+        if let Some(ready_at) = self.ready_at.take() {
+            let now = Instant::now();
+            if ready_at > now {
+                sleep(ready_at - now);
+            }
+        }
+    }
+
+    fn command(&mut self, payload: &[u8], delay: Duration) -> io::Result<()> {
+        let mut data = [0u8; MAX_PAYLOAD + 3];
+        let len = payload.len();
+        data[0] = HOST_ADDRESS;
+        data[1] = LENGTH_FLAG | len as u8;
+        data[2..2 + len].copy_from_slice(payload);
+        data[2 + len] = checksum(DISPLAY_WRITE_ADDRESS, &data[..2 + len]);
+
+        self.wait();
+        let res = self.inner.i2c_transfer(&mut [Message::Write {
+            address: DDC_CI_ADDRESS,
+            data: &data[..3 + len],
+            flags: WriteFlags::default(),
+        }]);
+        self.ready_at = Some(Instant::now() + delay);
+        res
+    }
+
+    /// Sends a request and reads its reply payload into `reply`.
+    ///
+    /// NULL replies and checksum mismatches are reported as `InvalidData`.
+    fn request(&mut self, payload: &[u8], delay: Duration, reply: &mut [u8; MAX_PAYLOAD]) -> io::Result<usize> {
+        self.command(payload, delay)?;
+
+        let mut data = [0u8; MAX_PAYLOAD + 3];
+        self.wait();
+        let res = self.inner.i2c_transfer(&mut [Message::Read {
+            address: DDC_CI_ADDRESS,
+            data: &mut data,
+            flags: ReadFlags::default(),
+        }]);
+        self.ready_at = Some(Instant::now() + DELAY_COMMAND);
+        res?;
+
+        let len = (data[1] & !LENGTH_FLAG) as usize;
+        if data[0] != DISPLAY_WRITE_ADDRESS || data[1] & LENGTH_FLAG == 0 || len > MAX_PAYLOAD {
+            Err(invalid_reply())
+        } else if checksum(HOST_READ_ADDRESS, &data[..2 + len]) != data[2 + len] {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "DDC/CI checksum mismatch"))
+        } else if len == 0 {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "DDC/CI NULL reply"))
+        } else {
+            reply[..len].copy_from_slice(&data[2..2 + len]);
+            Ok(len)
+        }
+    }
+}
+
+fn invalid_reply() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid DDC/CI reply")
+}
@@ -0,0 +1,347 @@
+// SMBus commands emulated with I2C transfers, for adapters that only
+// advertise `Functionality::I2C`, I2C block transfers emulated with SMBus
+// byte commands for adapters that lack both (when enabled with
+// `set_byte_block_emulation`), and simple I2C transfers
+// emulated with SMBus commands for adapters without `Functionality::I2C`.
+
+use {
+    crate::{
+        functionality::MissingFunctionality, smbus_pec_update, Functionality, I2c, Message, ReadFlags, ReadWrite,
+        WriteFlags,
+    },
+    i2c_linux_sys as i2c,
+    resize_slice::ResizeSlice,
+    std::{cmp, io, os::unix::io::AsRawFd},
+};
+
+// Command, count, data and PEC
+const WRITE_MAX: usize = i2c::I2C_SMBUS_BLOCK_MAX + 3;
+// Count, data and PEC
+const READ_MAX: usize = i2c::I2C_SMBUS_BLOCK_MAX + 2;
+
+impl<I: AsRawFd> I2c<I> {
+    // Whether an SMBus command must be emulated, failing if the adapter
+    // supports neither the command nor I2C transfers.
+    pub(crate) fn emulate_smbus(&mut self, required: Functionality) -> io::Result<bool> {
+        let func = self.functionality()?;
+        let pec = if self.pec {
+            Functionality::SMBUS_PEC
+        } else {
+            Functionality::empty()
+        };
+        if func.contains(required | pec) {
+            Ok(false)
+        } else if func.contains(Functionality::I2C) {
+            Ok(true)
+        } else {
+            // The kernel quietly skips PEC on adapters that cannot do it
+            MissingFunctionality::check(func, required).map(|()| false)
+        }
+    }
+
+    fn emulation_flags(&self) -> io::Result<(u16, WriteFlags, ReadFlags)> {
+        let address = self
+            .address
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "SMBus emulation requires a slave address"))?;
+
+        Ok(if self.address_10bit {
+            (address, WriteFlags::TENBIT_ADDR, ReadFlags::TENBIT_ADDR)
+        } else {
+            (address, WriteFlags::default(), ReadFlags::default())
+        })
+    }
+
+    pub(crate) fn emulated_write_quick(&mut self, read: bool) -> io::Result<()> {
+        let (address, write_flags, read_flags) = self.emulation_flags()?;
+        if read {
+            self.i2c_transfer(&mut [Message::Read {
+                address,
+                data: &mut [],
+                flags: read_flags,
+            }])
+        } else {
+            self.i2c_transfer(&mut [Message::Write {
+                address,
+                data: &[],
+                flags: write_flags,
+            }])
+        }
+    }
+
+    // Writes `write`, then reads exactly `read.len()` bytes after a repeated
+    // START unless `read` is empty, handling PEC when enabled.
+    pub(crate) fn emulated_transfer(&mut self, write: &[u8], read: &mut [u8]) -> io::Result<()> {
+        let (address, write_flags, read_flags) = self.emulation_flags()?;
+        let pec = self.pec as usize;
+        let crc = if write.is_empty() {
+            0
+        } else {
+            smbus_pec_update(smbus_pec_update(0, &[(address << 1) as u8]), write)
+        };
+
+        if read.is_empty() {
+            let mut buffer = [0u8; WRITE_MAX];
+            buffer[..write.len()].copy_from_slice(write);
+            buffer[write.len()] = crc;
+            return self.i2c_transfer(&mut [Message::Write {
+                address,
+                data: &buffer[..write.len() + pec],
+                flags: write_flags,
+            }])
+        }
+
+        let mut buffer = [0u8; READ_MAX];
+        let len = read.len() + pec;
+        {
+            let data = &mut buffer[..len];
+            if write.is_empty() {
+                self.i2c_transfer(&mut [Message::Read {
+                    address,
+                    data,
+                    flags: read_flags,
+                }])?;
+            } else {
+                self.i2c_transfer(&mut [
+                    Message::Write {
+                        address,
+                        data: write,
+                        flags: write_flags,
+                    },
+                    Message::Read {
+                        address,
+                        data,
+                        flags: read_flags,
+                    },
+                ])?;
+            }
+        }
+
+        if pec != 0 {
+            let crc = smbus_pec_update(crc, &[(address << 1) as u8 | 1]);
+            check_pec(crc, &buffer[..len])?;
+        }
+        read.copy_from_slice(&buffer[..read.len()]);
+        Ok(())
+    }
+
+    // Writes `write`, then reads a block whose length is given by its first
+    // byte, handling PEC when enabled. Returns the block length, of which at
+    // most `read.len()` bytes are copied out.
+    pub(crate) fn emulated_block_transfer(&mut self, write: &[u8], read: &mut [u8]) -> io::Result<usize> {
+        let (address, write_flags, read_flags) = self.emulation_flags()?;
+        let mut buffer = [0u8; READ_MAX];
+        let count = {
+            let mut messages = [
+                Message::Write {
+                    address,
+                    data: write,
+                    flags: write_flags,
+                },
+                Message::BlockRead {
+                    address,
+                    data: &mut buffer[..],
+                    flags: read_flags,
+                    pec: self.pec,
+                },
+            ];
+            self.i2c_transfer(&mut messages)?;
+            messages[1].len()
+        };
+
+        let copied = cmp::min(count, read.len());
+        read[..copied].copy_from_slice(&buffer[..copied]);
+        Ok(copied)
+    }
+
+    // Reads consecutive registers one byte at a time, assuming the device
+    // has one register per byte.
+    pub(crate) fn emulated_read_i2c_block(&mut self, command: u8, value: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(value.len(), i2c::I2C_SMBUS_BLOCK_MAX);
+        for (offset, byte) in value[..len].iter_mut().enumerate() {
+            *byte = self.smbus_read_byte_data(command.wrapping_add(offset as u8))?;
+        }
+        Ok(len)
+    }
+
+    // Writes consecutive registers one byte at a time, assuming the device
+    // has one register per byte.
+    pub(crate) fn emulated_write_i2c_block(&mut self, command: u8, value: &[u8]) -> io::Result<()> {
+        if value.len() > i2c::I2C_SMBUS_BLOCK_MAX {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "SMBus block too long"))
+        }
+
+        for (offset, &byte) in value.iter().enumerate() {
+            self.smbus_write_byte_data(command.wrapping_add(offset as u8), byte)?;
+        }
+        Ok(())
+    }
+
+    // Maps a transfer onto the SMBus command that produces the same bus
+    // traffic, failing with `Unsupported` if there is none. The slave address
+    // and PEC setting are restored afterwards.
+    pub(crate) fn emulated_i2c_transfer(&mut self, messages: &mut [Message]) -> io::Result<()> {
+        let target = match transfer_target(messages)? {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        // Only SMBus block reads carry a PEC byte
+        let pec = match *messages {
+            [_, Message::BlockRead { pec, .. }] => pec,
+            _ => false,
+        };
+
+        let previous = self.address.map(|address| (address, self.address_10bit));
+        let previous_pec = self.pec;
+        if previous != Some(target) {
+            self.smbus_set_slave_address(target.0, target.1)?;
+        }
+        let res = if pec == previous_pec {
+            self.smbus_transfer(messages)
+        } else {
+            self.smbus_set_pec(pec).and_then(|()| self.smbus_transfer(messages))
+        };
+
+        // Restore everything regardless, reporting the first failure
+        let restored_pec = if pec == previous_pec {
+            Ok(())
+        } else {
+            self.smbus_set_pec(previous_pec)
+        };
+        let restored_address = match previous {
+            Some((address, tenbit)) if previous != Some(target) => self.smbus_set_slave_address(address, tenbit),
+            _ => Ok(()),
+        };
+        res.and(restored_pec).and(restored_address)
+    }
+
+    fn smbus_transfer(&mut self, messages: &mut [Message]) -> io::Result<()> {
+        match *messages {
+            [Message::Write { data, .. }] => match data.len() {
+                0 => self.smbus_write_quick(ReadWrite::Write),
+                1 => self.smbus_write_byte(data[0]),
+                2 => self.smbus_write_byte_data(data[0], data[1]),
+                len if len <= i2c::I2C_SMBUS_BLOCK_MAX + 1 => {
+                    self.require(Functionality::SMBUS_WRITE_I2C_BLOCK)?;
+                    self.retry(|fd| i2c::i2c_smbus_write_i2c_block_data(fd, data[0], &data[1..]))
+                },
+                _ => Err(unsupported_transfer("writes are limited to 33 bytes")),
+            },
+            [Message::Read {
+                ref mut data, flags, ..
+            }] if !flags.contains(ReadFlags::RECEIVE_LEN) => match data.len() {
+                0 => self.smbus_write_quick(ReadWrite::Read),
+                1 => self.smbus_read_byte().map(|value| data[0] = value),
+                _ => Err(unsupported_transfer(
+                    "reads without a preceding register write are limited to 1 byte",
+                )),
+            },
+            [Message::Write { data: write, .. }, Message::Read {
+                data: ref mut read,
+                flags,
+                ..
+            }] if write.len() == 1 => {
+                let command = write[0];
+                if flags.contains(ReadFlags::RECEIVE_LEN) {
+                    if read.len() < i2c::I2C_SMBUS_BLOCK_MAX + 1 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "RECEIVE_LEN buffer too short",
+                        ))
+                    }
+                    let len = self.smbus_read_block_data(command, &mut read[1..])?;
+                    read[0] = len as u8;
+                    read.resize_to(1 + len);
+                    return Ok(())
+                }
+
+                match read.len() {
+                    0 => Err(unsupported_transfer("empty reads must not follow a register write")),
+                    1 => self.smbus_read_byte_data(command).map(|value| read[0] = value),
+                    2 if !self.functionality()?.contains(Functionality::SMBUS_READ_I2C_BLOCK) => self
+                        .smbus_read_word_data(command)
+                        .map(|value| read.copy_from_slice(&value.to_le_bytes())),
+                    len if len <= i2c::I2C_SMBUS_BLOCK_MAX => {
+                        self.require(Functionality::SMBUS_READ_I2C_BLOCK)?;
+                        let len = self.retry(|fd| i2c::i2c_smbus_read_i2c_block_data(fd, command, &mut read[..]))?;
+                        read.resize_to(len);
+                        Ok(())
+                    },
+                    _ => Err(unsupported_transfer("reads are limited to 32 bytes")),
+                }
+            },
+            [Message::Write { data: write, .. }, Message::BlockRead {
+                data: ref mut read,
+                pec,
+                ..
+            }] if write.len() == 1 => {
+                if pec {
+                    // The kernel silently skips PEC on adapters without it
+                    self.require(Functionality::SMBUS_PEC)?;
+                }
+                let len = self.smbus_read_block_data(write[0], read)?;
+                read.resize_to(len);
+                Ok(())
+            },
+            [Message::Write { data: write, .. }, Message::Read { data: ref mut read, .. }]
+                if write.len() == 3 && read.len() == 2 =>
+                self.smbus_process_call(write[0], u16::from_le_bytes([write[1], write[2]]))
+                    .map(|value| read.copy_from_slice(&value.to_le_bytes())),
+            _ => Err(unsupported_transfer(
+                "only a write, a read, or a register write followed by a read are supported",
+            )),
+        }
+    }
+}
+
+// The single device addressed by a transfer, if it has any messages.
+fn transfer_target(messages: &[Message]) -> io::Result<Option<(u16, bool)>> {
+    let mut target = None;
+    for (i, message) in messages.iter().enumerate() {
+        let tenbit = match *message {
+            Message::Read { flags, .. } | Message::BlockRead { flags, .. }
+                if (flags - ReadFlags::TENBIT_ADDR - ReadFlags::RECEIVE_LEN).is_empty() =>
+                flags.contains(ReadFlags::TENBIT_ADDR),
+            Message::Write { flags, .. } if (flags - WriteFlags::TENBIT_ADDR).is_empty() =>
+                flags.contains(WriteFlags::TENBIT_ADDR),
+            _ =>
+                return Err(unsupported_transfer(
+                    "message flags other than TENBIT_ADDR are not supported",
+                )),
+        };
+        let receive_len = match *message {
+            Message::Read { flags, .. } => flags.contains(ReadFlags::RECEIVE_LEN),
+            Message::BlockRead { .. } => true,
+            Message::Write { .. } => false,
+        };
+        if receive_len && i != 1 {
+            return Err(unsupported_transfer("block reads must follow a register write"))
+        }
+
+        let current = (message.address(), tenbit);
+        match target {
+            Some(target) if target != current =>
+                return Err(unsupported_transfer("all messages must address the same device")),
+            _ => target = Some(current),
+        }
+    }
+
+    Ok(target)
+}
+
+fn unsupported_transfer(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("I2C transfer cannot be emulated with SMBus commands: {}", reason),
+    )
+}
+
+// Verifies the PEC byte at the end of `data`, given the PEC of everything
+// sent before it.
+fn check_pec(crc: u8, data: &[u8]) -> io::Result<()> {
+    let (pec, data) = data.split_last().expect("PEC byte");
+    if smbus_pec_update(crc, data) == *pec {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "SMBus PEC mismatch"))
+    }
+}
@@ -3,7 +3,12 @@
 pub use udev::Device as UdevDevice;
 use {
     crate::I2c,
-    std::{fs::File, io, path::Path},
+    std::{
+        collections::HashMap,
+        fs::{self, File},
+        io,
+        path::{Path, PathBuf},
+    },
     udev,
 };
 
@@ -37,6 +42,38 @@ impl Enumerator {
         self.inner.scan_devices().map(|devices| DeviceIterator::new(devices))
     }
 
+    /// Builds the tree of root adapters and the kernel mux channels attached
+    /// beneath them.
+    ///
+    /// Mux channels whose parent adapter has no i2c device node are treated
+    /// as roots.
+    pub fn topology(&self) -> io::Result<Vec<TopologyNode>> {
+        let mut roots = Vec::new();
+        let mut children = HashMap::<u32, Vec<EnumeratedDevice>>::new();
+        let devices = self.iter()?.collect::<Vec<_>>();
+        let numbers = devices.iter().filter_map(|d| d.adapter_number()).collect::<Vec<_>>();
+        for device in devices {
+            match device.parent_adapter() {
+                Some(parent) if numbers.contains(&parent) => children.entry(parent).or_default().push(device),
+                _ => roots.push(device),
+            }
+        }
+
+        roots.sort_by_key(|d| d.adapter_number());
+        Ok(roots
+            .into_iter()
+            .map(|device| TopologyNode::build(device, &mut children))
+            .collect())
+    }
+
+    /// Finds the adapter at a physical path such as `i2c-1/mux@70/ch3`.
+    ///
+    /// See [EnumeratedDevice::physical_path].
+    pub fn find_by_physical_path(&self, path: &str) -> io::Result<Option<EnumeratedDevice>> {
+        let path = path.trim_matches('/');
+        Ok(self.iter()?.find(|d| d.physical_path().as_deref() == Some(path)))
+    }
+
     /// Retrieve the inner [udev::Enumerator].
     pub fn into_inner(self) -> udev::Enumerator {
         self.inner
@@ -87,6 +124,43 @@ impl EnumeratedDevice {
         self.device.devnode()
     }
 
+    /// The number of the adapter, as in `/dev/i2c-N`.
+    pub fn adapter_number(&self) -> Option<u32> {
+        self.device.sysnum().map(|n| n as u32)
+    }
+
+    /// The position of the adapter in the mux topology, if it is a channel
+    /// of a kernel mux.
+    pub fn mux_channel(&self) -> Option<MuxChannel> {
+        mux_channel(&self.adapter_dir()?).map(|(_, channel)| channel)
+    }
+
+    /// The number of the adapter that this mux channel's mux is attached to.
+    pub fn parent_adapter(&self) -> Option<u32> {
+        self.mux_channel().map(|m| m.parent_adapter)
+    }
+
+    /// The slave address of the mux that this channel belongs to.
+    pub fn mux_address(&self) -> Option<u16> {
+        self.mux_channel().map(|m| m.mux_address)
+    }
+
+    /// The index of this mux channel.
+    pub fn channel_index(&self) -> Option<u32> {
+        self.mux_channel().map(|m| m.channel)
+    }
+
+    /// The physical path to the adapter through any muxes, such as `i2c-1`
+    /// for a root adapter or `i2c-1/mux@70/ch3` for channel 3 of the mux at
+    /// address `0x70` on `i2c-1`.
+    pub fn physical_path(&self) -> Option<String> {
+        physical_path(&self.adapter_dir()?)
+    }
+
+    fn adapter_dir(&self) -> Option<PathBuf> {
+        self.device.parent().map(|adapter| adapter.syspath().to_owned())
+    }
+
     /// Open a new handle to the i2c device.
     pub fn open(&self) -> io::Result<I2c<File>> {
         self.path()
@@ -94,3 +168,78 @@ impl EnumeratedDevice {
             .and_then(I2c::from_path)
     }
 }
+
+/// The position of a kernel mux channel adapter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MuxChannel {
+    /// The number of the adapter that the mux is attached to.
+    pub parent_adapter: u32,
+    /// The slave address of the mux on its parent adapter.
+    pub mux_address: u16,
+    /// The channel index.
+    pub channel: u32,
+}
+
+/// An adapter and the mux channels attached beneath it.
+///
+/// Use [Enumerator::topology] to construct this.
+#[derive(Debug, Clone)]
+pub struct TopologyNode {
+    /// The adapter.
+    pub device: EnumeratedDevice,
+    /// Mux channels of muxes attached to the adapter, ordered by mux address
+    /// and channel index.
+    pub children: Vec<TopologyNode>,
+}
+
+impl TopologyNode {
+    fn build(device: EnumeratedDevice, children: &mut HashMap<u32, Vec<EnumeratedDevice>>) -> Self {
+        let mut nodes = device
+            .adapter_number()
+            .and_then(|number| children.remove(&number))
+            .unwrap_or_default();
+        nodes.sort_by_key(|d| d.mux_channel().map(|m| (m.mux_address, m.channel)));
+
+        TopologyNode {
+            children: nodes.into_iter().map(|child| Self::build(child, children)).collect(),
+            device,
+        }
+    }
+}
+
+fn adapter_number(adapter: &Path) -> Option<u32> {
+    adapter.file_name()?.to_str()?.strip_prefix("i2c-")?.parse().ok()
+}
+
+/// Locates the mux that `adapter` is a channel of, returning the parent
+/// adapter's directory.
+fn mux_channel(adapter: &Path) -> Option<(PathBuf, MuxChannel)> {
+    let mux = fs::canonicalize(adapter.join("mux_device")).ok()?;
+    let parent = mux.parent()?;
+    // Mux clients are named after their bus and address, as in `1-0070`
+    let (_, address) = mux.file_name()?.to_str()?.split_once('-')?;
+    let adapter = fs::canonicalize(adapter).ok()?;
+    let channel = fs::read_dir(&mux)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| fs::canonicalize(entry.path()).ok().as_ref() == Some(&adapter))
+        .find_map(|entry| entry.file_name().to_str()?.strip_prefix("channel-")?.parse().ok())?;
+
+    Some((parent.to_owned(), MuxChannel {
+        parent_adapter: adapter_number(parent)?,
+        mux_address: u16::from_str_radix(address, 16).ok()?,
+        channel,
+    }))
+}
+
+fn physical_path(adapter: &Path) -> Option<String> {
+    match mux_channel(adapter) {
+        Some((parent, channel)) => Some(format!(
+            "{}/mux@{:02x}/ch{}",
+            physical_path(&parent)?,
+            channel.mux_address,
+            channel.channel
+        )),
+        None => Some(adapter.file_name()?.to_str()?.to_owned()),
+    }
+}
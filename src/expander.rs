@@ -0,0 +1,460 @@
+//! GPIO expanders: PCA9555, PCF8574 and MCP23017.
+//!
+//! Output latches and pin configuration are kept in shadow registers, so
+//! changing a single pin never reads back the port, which could otherwise
+//! pick up the level of an input pin or race with another pin's update.
+//!
+//! Pins are numbered from `0`, with port 1 (or port B) following port 0
+//! (or port A) on 16 pin devices.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use {
+//!     i2c_linux::{expander::{Chip, Direction, Expander, Pin}, I2c},
+//!     std::cell::RefCell,
+//! };
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let expander = RefCell::new(Expander::new(I2c::from_path("/dev/i2c-1")?, Chip::Pca9555, 0x20)?);
+//! let mut led = Pin::new(&expander, 0)?;
+//! let mut button = Pin::new(&expander, 8)?;
+//! led.set_direction(Direction::Output)?;
+//! led.set(button.is_low()?)?;
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::I2c,
+    std::{cell::RefCell, io, os::unix::io::AsRawFd},
+};
+
+/// Supported GPIO expander chips.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Chip {
+    /// 16 pin expander with polarity inversion.
+    Pca9555,
+    /// 8 pin quasi-bidirectional expander.
+    Pcf8574,
+    /// 16 pin expander with polarity inversion and pull-ups, with its
+    /// registers in the default `IOCON.BANK = 0` layout.
+    Mcp23017,
+}
+
+struct Registers {
+    input: u8,
+    output: u8,
+    direction: u8,
+    polarity: u8,
+    pull_up: Option<u8>,
+}
+
+const PCA9555_REGISTERS: Registers = Registers {
+    input: 0x00,
+    output: 0x02,
+    polarity: 0x04,
+    direction: 0x06,
+    pull_up: None,
+};
+
+const MCP23017_REGISTERS: Registers = Registers {
+    direction: 0x00,
+    polarity: 0x02,
+    pull_up: Some(0x0c),
+    input: 0x12,
+    output: 0x14,
+};
+
+impl Chip {
+    /// The number of GPIO pins.
+    pub fn pins(self) -> u8 {
+        match self {
+            Chip::Pcf8574 => 8,
+            Chip::Pca9555 | Chip::Mcp23017 => 16,
+        }
+    }
+
+    /// Whether the chip can invert the polarity of its inputs.
+    pub fn has_polarity(self) -> bool {
+        self.registers().is_some()
+    }
+
+    /// Whether the chip has configurable pull-up resistors.
+    pub fn has_pull_ups(self) -> bool {
+        self.registers().and_then(|r| r.pull_up).is_some()
+    }
+
+    fn registers(self) -> Option<&'static Registers> {
+        match self {
+            Chip::Pca9555 => Some(&PCA9555_REGISTERS),
+            Chip::Mcp23017 => Some(&MCP23017_REGISTERS),
+            Chip::Pcf8574 => None,
+        }
+    }
+
+    fn mask(self) -> u16 {
+        match self.pins() {
+            8 => 0x00ff,
+            _ => 0xffff,
+        }
+    }
+}
+
+/// The direction of a GPIO pin.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// The pin is an input.
+    Input,
+    /// The pin drives its output latch.
+    Output,
+}
+
+/// A GPIO expander.
+pub struct Expander<I> {
+    inner: I2c<I>,
+    chip: Chip,
+    output: u16,
+    inputs: u16,
+    polarity: u16,
+    pull_ups: u16,
+}
+
+impl<I: AsRawFd> Expander<I> {
+    /// Creates a handle for the chip at `address`, loading its current
+    /// configuration into the shadow registers.
+    pub fn new(mut i2c: I2c<I>, chip: Chip, address: u16) -> io::Result<Self> {
+        i2c.smbus_set_slave_address(address, false)?;
+
+        let mut expander = Expander {
+            inner: i2c,
+            chip,
+            // Power on defaults: all pins are inputs
+            output: chip.mask(),
+            inputs: chip.mask(),
+            polarity: 0,
+            pull_ups: 0,
+        };
+        expander.sync()?;
+
+        Ok(expander)
+    }
+
+    /// Reloads the shadow registers from the chip.
+    ///
+    /// The PCF8574 cannot report its output latch, so its shadow registers
+    /// are left untouched.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if let Some(registers) = self.chip.registers() {
+            self.output = self.read_register(registers.output)?;
+            self.inputs = self.read_register(registers.direction)?;
+            self.polarity = self.read_register(registers.polarity)?;
+            if let Some(pull_up) = registers.pull_up {
+                self.pull_ups = self.read_register(pull_up)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the level of all pins, after any polarity inversion.
+    pub fn read(&mut self) -> io::Result<u16> {
+        match self.chip.registers() {
+            Some(registers) => self.read_register(registers.input),
+            None => self.inner.smbus_read_byte().map(u16::from),
+        }
+    }
+
+    /// Reads the level of a single pin.
+    pub fn read_pin(&mut self, pin: u8) -> io::Result<bool> {
+        let bit = self.bit(pin)?;
+        self.read().map(|value| value & bit != 0)
+    }
+
+    /// Sets the output latches of all pins.
+    pub fn write(&mut self, value: u16) -> io::Result<()> {
+        self.write_masked(self.chip.mask(), value)
+    }
+
+    /// Sets the output latches of the pins in `mask`, leaving the others
+    /// unchanged.
+    pub fn write_masked(&mut self, mask: u16, value: u16) -> io::Result<()> {
+        let output = (self.output & !mask | value & mask) & self.chip.mask();
+        match self.chip.registers() {
+            Some(registers) => self.write_register(registers.output, output)?,
+            // Quasi-bidirectional pins must be left high to act as inputs
+            None => self.inner.smbus_write_byte((output | self.inputs) as u8)?,
+        }
+        self.output = output;
+        Ok(())
+    }
+
+    /// Sets the output latch of a single pin.
+    pub fn write_pin(&mut self, pin: u8, value: bool) -> io::Result<()> {
+        let bit = self.bit(pin)?;
+        self.write_masked(bit, if value { bit } else { 0 })
+    }
+
+    /// Configures the pins in `inputs` as inputs, and all others as outputs.
+    pub fn set_inputs(&mut self, inputs: u16) -> io::Result<()> {
+        let inputs = inputs & self.chip.mask();
+        match self.chip.registers() {
+            Some(registers) => self.write_register(registers.direction, inputs)?,
+            None => self.inner.smbus_write_byte((self.output | inputs) as u8)?,
+        }
+        self.inputs = inputs;
+        Ok(())
+    }
+
+    /// Sets the direction of a single pin.
+    pub fn set_pin_direction(&mut self, pin: u8, direction: Direction) -> io::Result<()> {
+        let bit = self.bit(pin)?;
+        match direction {
+            Direction::Input => self.set_inputs(self.inputs | bit),
+            Direction::Output => self.set_inputs(self.inputs & !bit),
+        }
+    }
+
+    /// Inverts the polarity of the inputs in `inverted`.
+    pub fn set_polarity(&mut self, inverted: u16) -> io::Result<()> {
+        let registers = self.chip.registers().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "expander does not support polarity inversion",
+            )
+        })?;
+        let inverted = inverted & self.chip.mask();
+        self.write_register(registers.polarity, inverted)?;
+        self.polarity = inverted;
+        Ok(())
+    }
+
+    /// Sets whether a single input is inverted.
+    pub fn set_pin_polarity(&mut self, pin: u8, inverted: bool) -> io::Result<()> {
+        let bit = self.bit(pin)?;
+        self.set_polarity(if inverted {
+            self.polarity | bit
+        } else {
+            self.polarity & !bit
+        })
+    }
+
+    /// Enables the pull-up resistors of the pins in `enabled`.
+    pub fn set_pull_ups(&mut self, enabled: u16) -> io::Result<()> {
+        let register = self.chip.registers().and_then(|r| r.pull_up).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "expander does not support configurable pull-ups",
+            )
+        })?;
+        let enabled = enabled & self.chip.mask();
+        self.write_register(register, enabled)?;
+        self.pull_ups = enabled;
+        Ok(())
+    }
+
+    /// Sets whether a single pin's pull-up resistor is enabled.
+    pub fn set_pin_pull_up(&mut self, pin: u8, enabled: bool) -> io::Result<()> {
+        let bit = self.bit(pin)?;
+        self.set_pull_ups(if enabled {
+            self.pull_ups | bit
+        } else {
+            self.pull_ups & !bit
+        })
+    }
+
+    fn read_register(&mut self, register: u8) -> io::Result<u16> {
+        // Both chips advance to the paired port register within a word access
+        self.inner.smbus_read_word_data(register)
+    }
+
+    fn write_register(&mut self, register: u8, value: u16) -> io::Result<()> {
+        self.inner.smbus_write_word_data(register, value)
+    }
+}
+
+impl<I> Expander<I> {
+    /// The chip type.
+    pub fn chip(&self) -> Chip {
+        self.chip
+    }
+
+    /// The shadowed output latches.
+    pub fn output(&self) -> u16 {
+        self.output
+    }
+
+    /// The shadowed mask of input pins.
+    pub fn inputs(&self) -> u16 {
+        self.inputs
+    }
+
+    /// The shadowed mask of inverted inputs.
+    pub fn polarity(&self) -> u16 {
+        self.polarity
+    }
+
+    /// The shadowed mask of enabled pull-ups.
+    pub fn pull_ups(&self) -> u16 {
+        self.pull_ups
+    }
+
+    /// Returns the underlying adapter.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying adapter.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying adapter.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+
+    fn bit(&self, pin: u8) -> io::Result<u16> {
+        if pin < self.chip.pins() {
+            Ok(1 << pin)
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "expander pin out of range"))
+        }
+    }
+}
+
+/// A handle to a single pin of a shared [Expander].
+pub struct Pin<'a, I> {
+    expander: &'a RefCell<Expander<I>>,
+    pin: u8,
+}
+
+impl<'a, I: AsRawFd> Pin<'a, I> {
+    /// Creates a handle to `pin` of `expander`.
+    pub fn new(expander: &'a RefCell<Expander<I>>, pin: u8) -> io::Result<Self> {
+        expander.borrow().bit(pin)?;
+
+        Ok(Pin { expander, pin })
+    }
+
+    /// Sets the direction of the pin.
+    pub fn set_direction(&mut self, direction: Direction) -> io::Result<()> {
+        self.expander.borrow_mut().set_pin_direction(self.pin, direction)
+    }
+
+    /// Sets the output latch of the pin.
+    pub fn set(&mut self, value: bool) -> io::Result<()> {
+        self.expander.borrow_mut().write_pin(self.pin, value)
+    }
+
+    /// Sets the output latch of the pin high.
+    pub fn set_high(&mut self) -> io::Result<()> {
+        self.set(true)
+    }
+
+    /// Sets the output latch of the pin low.
+    pub fn set_low(&mut self) -> io::Result<()> {
+        self.set(false)
+    }
+
+    /// Flips the output latch of the pin.
+    pub fn toggle(&mut self) -> io::Result<()> {
+        let value = self.is_set_high();
+        self.set(!value)
+    }
+
+    /// Reads the level of the pin.
+    pub fn is_high(&mut self) -> io::Result<bool> {
+        self.expander.borrow_mut().read_pin(self.pin)
+    }
+
+    /// Reads the level of the pin.
+    pub fn is_low(&mut self) -> io::Result<bool> {
+        self.is_high().map(|high| !high)
+    }
+
+    /// Sets whether the pin's input is inverted.
+    pub fn set_inverted(&mut self, inverted: bool) -> io::Result<()> {
+        self.expander.borrow_mut().set_pin_polarity(self.pin, inverted)
+    }
+
+    /// Sets whether the pin's pull-up resistor is enabled.
+    pub fn set_pull_up(&mut self, enabled: bool) -> io::Result<()> {
+        self.expander.borrow_mut().set_pin_pull_up(self.pin, enabled)
+    }
+}
+
+impl<'a, I> Pin<'a, I> {
+    /// The pin number.
+    pub fn index(&self) -> u8 {
+        self.pin
+    }
+
+    /// Whether the shadowed output latch of the pin is high.
+    pub fn is_set_high(&self) -> bool {
+        self.expander.borrow().output & (1 << self.pin) != 0
+    }
+}
+
+/// An I/O error surfaced through the `embedded-hal` digital traits.
+#[cfg(feature = "embedded-hal")]
+#[cfg_attr(feature = "doc", doc(cfg(feature = "embedded-hal")))]
+#[derive(Debug)]
+pub struct PinError(pub io::Error);
+
+#[cfg(feature = "embedded-hal")]
+impl From<io::Error> for PinError {
+    fn from(e: io::Error) -> Self {
+        PinError(e)
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+impl embedded_hal::digital::Error for PinError {
+    fn kind(&self) -> embedded_hal::digital::ErrorKind {
+        embedded_hal::digital::ErrorKind::Other
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+#[cfg_attr(feature = "doc", doc(cfg(feature = "embedded-hal")))]
+impl<'a, I> embedded_hal::digital::ErrorType for Pin<'a, I> {
+    type Error = PinError;
+}
+
+#[cfg(feature = "embedded-hal")]
+#[cfg_attr(feature = "doc", doc(cfg(feature = "embedded-hal")))]
+impl<'a, I: AsRawFd> embedded_hal::digital::OutputPin for Pin<'a, I> {
+    fn set_low(&mut self) -> Result<(), PinError> {
+        Pin::set_low(self).map_err(From::from)
+    }
+
+    fn set_high(&mut self) -> Result<(), PinError> {
+        Pin::set_high(self).map_err(From::from)
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+#[cfg_attr(feature = "doc", doc(cfg(feature = "embedded-hal")))]
+impl<'a, I: AsRawFd> embedded_hal::digital::StatefulOutputPin for Pin<'a, I> {
+    fn is_set_high(&mut self) -> Result<bool, PinError> {
+        Ok(Pin::is_set_high(self))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, PinError> {
+        Ok(!Pin::is_set_high(self))
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+#[cfg_attr(feature = "doc", doc(cfg(feature = "embedded-hal")))]
+impl<'a, I: AsRawFd> embedded_hal::digital::InputPin for Pin<'a, I> {
+    fn is_high(&mut self) -> Result<bool, PinError> {
+        Pin::is_high(self).map_err(From::from)
+    }
+
+    fn is_low(&mut self) -> Result<bool, PinError> {
+        Pin::is_low(self).map_err(From::from)
+    }
+}
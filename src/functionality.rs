@@ -0,0 +1,136 @@
+//! Cached adapter capabilities.
+//!
+//! An adapter's [Functionality] never changes, so it is queried once per
+//! `I2c` handle and reused by every operation that needs to pick between
+//! SMBus commands and I2C transfers.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{functionality::MissingFunctionality, Functionality, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut i2c = I2c::from_path("/dev/i2c-1")?;
+//! println!("blocks of up to {} bytes", i2c.max_block_len()?);
+//! if let Err(e) = i2c.require(Functionality::SMBUS_PEC) {
+//!     if let Some(missing) = e.get_ref().and_then(|e| e.downcast_ref::<MissingFunctionality>()) {
+//!         println!("missing {:?}", missing.missing);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{Functionality, I2c},
+    i2c_linux_sys as i2c,
+    std::{error, fmt, io, os::unix::io::AsRawFd},
+};
+
+/// The error carried by `Unsupported` errors when an adapter lacks the
+/// functionality an operation needs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MissingFunctionality {
+    /// The functionality the operation needs.
+    pub required: Functionality,
+    /// The part of `required` that the adapter lacks.
+    pub missing: Functionality,
+}
+
+impl MissingFunctionality {
+    /// Checks `available` against `required`.
+    ///
+    /// ```rust
+    /// use i2c_linux::{functionality::MissingFunctionality, Functionality};
+    ///
+    /// let available = Functionality::I2C | Functionality::SMBUS_QUICK;
+    /// assert!(MissingFunctionality::check(available, Functionality::I2C).is_ok());
+    /// let e = MissingFunctionality::check(available, Functionality::I2C | Functionality::SMBUS_PEC).unwrap_err();
+    /// assert_eq!(e.kind(), std::io::ErrorKind::Unsupported);
+    /// ```
+    pub fn check(available: Functionality, required: Functionality) -> io::Result<()> {
+        let missing = required - available;
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Unsupported, MissingFunctionality {
+                required,
+                missing,
+            }))
+        }
+    }
+}
+
+impl fmt::Display for MissingFunctionality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "adapter lacks required functionality {:?}", self.missing)
+    }
+}
+
+impl error::Error for MissingFunctionality {}
+
+impl<I: AsRawFd> I2c<I> {
+    /// The capabilities of the adapter, queried on first use and cached by
+    /// the handle.
+    pub fn functionality(&mut self) -> io::Result<Functionality> {
+        match self.functionality {
+            Some(func) => Ok(func),
+            None => {
+                let func = self.i2c_functionality()?;
+                self.functionality = Some(func);
+                Ok(func)
+            },
+        }
+    }
+
+    /// The cached capabilities, if they have been queried.
+    pub fn cached_functionality(&self) -> Option<Functionality> {
+        self.functionality
+    }
+
+    /// Fails with `Unsupported` if the adapter lacks any of `required`.
+    ///
+    /// The error wraps a [MissingFunctionality].
+    pub fn require(&mut self, required: Functionality) -> io::Result<()> {
+        MissingFunctionality::check(self.functionality()?, required)
+    }
+
+    /// Whether the adapter supports `smbus_read_block_data` natively.
+    pub fn supports_smbus_block_read(&mut self) -> io::Result<bool> {
+        self.functionality()
+            .map(|func| func.contains(Functionality::SMBUS_READ_BLOCK_DATA))
+    }
+
+    /// Whether the adapter supports combined transfers through
+    /// `i2c_transfer`.
+    pub fn supports_i2c_transfer(&mut self) -> io::Result<bool> {
+        self.functionality().map(|func| func.contains(Functionality::I2C))
+    }
+
+    /// The longest block that `i2c_read_block_data` and
+    /// `i2c_write_block_data` can transfer in one call.
+    ///
+    /// Adapters that support I2C transfers are limited by the 16-bit message
+    /// length, including the command byte. SMBus adapters are limited to
+    /// `I2C_SMBUS_BLOCK_MAX`, provided they support I2C block commands or
+    /// `set_byte_block_emulation()` allows byte commands in their place, and
+    /// to nothing at all otherwise.
+    pub fn max_block_len(&mut self) -> io::Result<usize> {
+        let func = self.functionality()?;
+        let emulate = self.byte_block_emulation();
+        let block = |block, byte| func.contains(block) || (emulate && func.contains(byte));
+        Ok(if func.contains(Functionality::I2C) {
+            u16::MAX as usize - 1
+        } else if block(Functionality::SMBUS_READ_I2C_BLOCK, Functionality::SMBUS_READ_BYTE_DATA)
+            && block(
+                Functionality::SMBUS_WRITE_I2C_BLOCK,
+                Functionality::SMBUS_WRITE_BYTE_DATA,
+            )
+        {
+            i2c::I2C_SMBUS_BLOCK_MAX
+        } else {
+            0
+        })
+    }
+}
@@ -0,0 +1,261 @@
+//! HID over I2C host protocol.
+//!
+//! A HID over I2C device exposes a HID descriptor at a device specific
+//! register, usually described by ACPI or the device tree, which locates
+//! the report descriptor, input and command registers.
+//!
+//! The kernel `i2c-hid` driver must not be bound to the device.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{hid::{Hid, PowerState}, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut hid = Hid::open(I2c::from_path("/dev/i2c-1")?, 0x38, 0x0001)?;
+//! hid.set_power(PowerState::On)?;
+//! hid.reset()?;
+//! let report_descriptor = hid.read_report_descriptor()?;
+//! while let Some(report) = hid.read_input_report()? {
+//!     println!("{:02x?}", report);
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{I2c, Message, ReadFlags, WriteFlags},
+    std::{io, os::unix::io::AsRawFd, thread::sleep, time::Duration},
+};
+
+const OPCODE_RESET: u8 = 0x01;
+const OPCODE_SET_POWER: u8 = 0x08;
+
+// Time allowed for a device to complete a reset
+const RESET_DELAY: Duration = Duration::from_millis(100);
+
+/// The HID descriptor of a HID over I2C device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HidDescriptor {
+    /// The HID over I2C protocol version, in BCD.
+    pub version: u16,
+    /// The length of the report descriptor.
+    pub report_desc_length: u16,
+    /// The register holding the report descriptor.
+    pub report_desc_register: u16,
+    /// The register that input reports are read from.
+    pub input_register: u16,
+    /// The length of the largest input report, including its length prefix.
+    pub max_input_length: u16,
+    /// The register that output reports are written to.
+    pub output_register: u16,
+    /// The length of the largest output report, including its length prefix.
+    pub max_output_length: u16,
+    /// The register that commands are written to.
+    pub command_register: u16,
+    /// The register used for command data.
+    pub data_register: u16,
+    /// USB vendor ID.
+    pub vendor_id: u16,
+    /// Product ID.
+    pub product_id: u16,
+    /// Product version.
+    pub version_id: u16,
+}
+
+impl HidDescriptor {
+    /// The length of the HID descriptor.
+    pub const LEN: usize = 30;
+
+    /// Parses a HID descriptor.
+    ///
+    /// ```rust
+    /// use i2c_linux::hid::HidDescriptor;
+    ///
+    /// let desc = HidDescriptor::parse(&[
+    ///     0x1e, 0x00, 0x00, 0x01, 0x6a, 0x02, 0x02, 0x00, 0x03, 0x00, 0x42, 0x00, 0x04, 0x00, 0x08, 0x00,
+    ///     0x05, 0x00, 0x06, 0x00, 0x6d, 0x04, 0x2a, 0xc0, 0x03, 0x01, 0x00, 0x00, 0x00, 0x00,
+    /// ])
+    /// .unwrap();
+    /// assert_eq!(desc.version, 0x0100);
+    /// assert_eq!((desc.report_desc_register, desc.report_desc_length), (0x0002, 0x026a));
+    /// assert_eq!((desc.vendor_id, desc.product_id), (0x046d, 0xc02a));
+    /// ```
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < Self::LEN {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated HID descriptor"))
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        if word(0) as usize != Self::LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid HID descriptor length",
+            ))
+        }
+        if word(2) != 0x0100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported HID over I2C version",
+            ))
+        }
+
+        Ok(HidDescriptor {
+            version: word(2),
+            report_desc_length: word(4),
+            report_desc_register: word(6),
+            input_register: word(8),
+            max_input_length: word(10),
+            output_register: word(12),
+            max_output_length: word(14),
+            command_register: word(16),
+            data_register: word(18),
+            vendor_id: word(20),
+            product_id: word(22),
+            version_id: word(24),
+        })
+    }
+}
+
+/// Device power states set with [Hid::set_power].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PowerState {
+    /// Fully powered.
+    On,
+    /// Low power, waking on input.
+    Sleep,
+}
+
+/// A HID over I2C device.
+pub struct Hid<I> {
+    inner: I2c<I>,
+    address: u16,
+    descriptor: HidDescriptor,
+}
+
+impl<I: AsRawFd> Hid<I> {
+    /// Opens the device at `address`, reading its HID descriptor from
+    /// `descriptor_register`.
+    pub fn open(mut i2c: I2c<I>, address: u16, descriptor_register: u16) -> io::Result<Self> {
+        let mut data = [0u8; HidDescriptor::LEN];
+        read_register(&mut i2c, address, descriptor_register, &mut data)?;
+
+        Ok(Hid {
+            inner: i2c,
+            address,
+            descriptor: HidDescriptor::parse(&data)?,
+        })
+    }
+
+    /// Reads the report descriptor.
+    pub fn read_report_descriptor(&mut self) -> io::Result<Vec<u8>> {
+        let mut data = vec![0u8; self.descriptor.report_desc_length as usize];
+        read_register(
+            &mut self.inner,
+            self.address,
+            self.descriptor.report_desc_register,
+            &mut data,
+        )?;
+        Ok(data)
+    }
+
+    /// Sets the device's power state.
+    pub fn set_power(&mut self, state: PowerState) -> io::Result<()> {
+        let state = match state {
+            PowerState::On => 0,
+            PowerState::Sleep => 1,
+        };
+        self.command(OPCODE_SET_POWER, state)
+    }
+
+    /// Resets the device, then discards the reset response from the input
+    /// register.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.command(OPCODE_RESET, 0)?;
+        sleep(RESET_DELAY);
+        self.read_input_report().map(drop)
+    }
+
+    /// Reads an input report, without its length prefix.
+    ///
+    /// Returns `None` if the device has no report pending or has just been
+    /// reset. Without an interrupt line, this must be polled.
+    pub fn read_input_report(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut data = vec![0u8; (self.descriptor.max_input_length as usize).max(2)];
+        self.inner.i2c_transfer(&mut [Message::Read {
+            address: self.address,
+            data: &mut data,
+            flags: ReadFlags::default(),
+        }])?;
+
+        let len = u16::from_le_bytes([data[0], data[1]]) as usize;
+        match len {
+            0 | 2 => Ok(None),
+            1 => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid HID input report length",
+            )),
+            len if len > data.len() => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "HID input report exceeds maximum length",
+            )),
+            len => {
+                data.truncate(len);
+                data.drain(..2);
+                Ok(Some(data))
+            },
+        }
+    }
+
+    fn command(&mut self, opcode: u8, low: u8) -> io::Result<()> {
+        let [reg_lo, reg_hi] = self.descriptor.command_register.to_le_bytes();
+        self.inner.i2c_transfer(&mut [Message::Write {
+            address: self.address,
+            data: &[reg_lo, reg_hi, low, opcode],
+            flags: WriteFlags::default(),
+        }])
+    }
+}
+
+fn read_register<I: AsRawFd>(i2c: &mut I2c<I>, address: u16, register: u16, data: &mut [u8]) -> io::Result<()> {
+    i2c.i2c_transfer(&mut [
+        Message::Write {
+            address,
+            data: &register.to_le_bytes(),
+            flags: WriteFlags::default(),
+        },
+        Message::Read {
+            address,
+            data,
+            flags: ReadFlags::default(),
+        },
+    ])
+}
+
+impl<I> Hid<I> {
+    /// The device's HID descriptor.
+    pub fn descriptor(&self) -> &HidDescriptor {
+        &self.descriptor
+    }
+
+    /// The slave address of the device.
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// Returns the underlying adapter.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying adapter.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying adapter.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
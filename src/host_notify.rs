@@ -0,0 +1,201 @@
+//! SMBus Host Notify event reception.
+//!
+//! A device raises a Host Notify event by writing its own address and a data
+//! word to the SMBus host address.
+//!
+//! Adapters that advertise `Functionality::SMBUS_HOST_NOTIFY` handle these
+//! events in the kernel and deliver them to the driver bound to the notifying
+//! device, so they never reach i2c-dev. To receive them in userspace instead,
+//! bind a `slave-mqueue` slave backend to the host address of an adapter that
+//! supports `Functionality::SLAVE`, which [HostNotify::enable] checks, and
+//! [forward](Notifier::forward) its frames through a [Notifier]. Notifiers
+//! can also inject synthetic events, which allows consumers to be tested
+//! without hardware.
+//!
+//! # Example
+//!
+//! ```rust
+//! use i2c_linux::host_notify::{HostNotify, HostNotifyEvent};
+//!
+//! let (notifier, notify) = HostNotify::channel();
+//! notifier.notify(0x2c, 0x1234).unwrap();
+//! assert_eq!(notify.try_recv(), Some(HostNotifyEvent { address: 0x2c, data: 0x1234 }));
+//! assert_eq!(notify.try_recv(), None);
+//! drop(notifier);
+//! assert_eq!(notify.recv(), None);
+//! ```
+//!
+//! Receiving events through a slave backend, created with
+//! `echo slave-mqueue 0x1008 > /sys/bus/i2c/devices/i2c-1/new_device`:
+//!
+//! ```rust,no_run
+//! use {
+//!     i2c_linux::{host_notify::HostNotify, mctp::SlaveMqueue, I2c},
+//!     std::thread,
+//! };
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut i2c = I2c::from_path("/dev/i2c-1")?;
+//! let (notifier, notify) = HostNotify::enable(&mut i2c)?;
+//! let mut queue = SlaveMqueue::open("/sys/bus/i2c/devices/1-1008/slave-mqueue")?;
+//! thread::spawn(move || -> ::std::io::Result<()> {
+//!     loop {
+//!         queue.wait(None)?;
+//!         notifier.forward(&mut queue)?;
+//!     }
+//! });
+//! while let Some(event) = notify.recv() {
+//!     println!("{:#04x} notified {:#06x}", event.address, event.data);
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{mctp::FrameSource, Functionality, I2c},
+    std::{
+        io,
+        os::unix::io::AsRawFd,
+        sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        time::Duration,
+    },
+};
+
+/// The SMBus host address that Host Notify messages are written to.
+pub const SMBUS_HOST_ADDRESS: u16 = 0x08;
+
+/// A Host Notify message received from a device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HostNotifyEvent {
+    /// The slave address of the notifying device.
+    pub address: u16,
+    /// The data word sent with the notification.
+    pub data: u16,
+}
+
+/// Receives Host Notify events.
+pub struct HostNotify {
+    receiver: Receiver<HostNotifyEvent>,
+}
+
+impl HostNotify {
+    /// Creates a receiver along with a handle that delivers events to it.
+    ///
+    /// The receiver is disconnected once every [Notifier] has been dropped.
+    pub fn channel() -> (Notifier, Self) {
+        let (sender, receiver) = channel();
+        (Notifier { sender }, HostNotify { receiver })
+    }
+
+    /// Creates a channel for events received on an adapter, ensuring that it
+    /// supports the slave mode needed to listen on the host address.
+    ///
+    /// Fails with `Unsupported` otherwise. Adapters that only advertise
+    /// `Functionality::SMBUS_HOST_NOTIFY` deliver events to kernel drivers,
+    /// not to userspace, so that alone is not enough.
+    pub fn enable<I: AsRawFd>(i2c: &mut I2c<I>) -> io::Result<(Notifier, Self)> {
+        i2c.require(Functionality::SLAVE)?;
+        Ok(Self::channel())
+    }
+
+    /// Returns the next pending event, if any.
+    pub fn try_recv(&self) -> Option<HostNotifyEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Waits for the next event, or until `timeout` elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<HostNotifyEvent> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    /// Waits for the next event.
+    ///
+    /// Returns `None` once every [Notifier] has been dropped.
+    pub fn recv(&self) -> Option<HostNotifyEvent> {
+        self.receiver.recv().ok()
+    }
+
+    /// Iterates over pending events without blocking.
+    pub fn try_iter(&self) -> impl Iterator<Item = HostNotifyEvent> + '_ {
+        self.receiver.try_iter()
+    }
+}
+
+/// Delivers Host Notify events to a [HostNotify] receiver.
+#[derive(Clone)]
+pub struct Notifier {
+    sender: Sender<HostNotifyEvent>,
+}
+
+impl Notifier {
+    /// Delivers an event from the device at `address`.
+    ///
+    /// Fails if the receiver has been dropped.
+    pub fn notify(&self, address: u16, data: u16) -> io::Result<()> {
+        self.send(HostNotifyEvent { address, data })
+    }
+
+    /// Delivers an event.
+    pub fn send(&self, event: HostNotifyEvent) -> io::Result<()> {
+        self.sender
+            .send(event)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Host Notify receiver dropped"))
+    }
+
+    /// Parses and delivers the payload of a Host Notify message, as written
+    /// to the host address: the device's address byte followed by the data
+    /// word, least significant byte first.
+    ///
+    /// ```rust
+    /// use i2c_linux::host_notify::{HostNotify, HostNotifyEvent};
+    ///
+    /// let (notifier, notify) = HostNotify::channel();
+    /// notifier.notify_raw(&[0x58, 0x34, 0x12]).unwrap();
+    /// assert_eq!(notify.try_recv(), Some(HostNotifyEvent { address: 0x2c, data: 0x1234 }));
+    /// ```
+    pub fn notify_raw(&self, message: &[u8]) -> io::Result<()> {
+        match *message {
+            [address, lo, hi] => self.notify((address >> 1) as u16, u16::from_le_bytes([lo, hi])),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid SMBus Host Notify message length",
+            )),
+        }
+    }
+
+    /// Delivers every Host Notify message queued by a slave backend, whose
+    /// frames begin with the destination address byte. Frames addressed
+    /// elsewhere are discarded, as are malformed Host Notify messages.
+    ///
+    /// Returns the number of events delivered, and only fails if the source
+    /// does or the receiver has been dropped.
+    ///
+    /// ```rust
+    /// use i2c_linux::{host_notify::{HostNotify, HostNotifyEvent}, mctp::Capture};
+    ///
+    /// let mut capture = Capture::new(&b"10 58 34\n10 58 34 12\n20 01 02\n"[..]);
+    /// let (notifier, notify) = HostNotify::channel();
+    /// assert_eq!(notifier.forward(&mut capture).unwrap(), 1);
+    /// assert_eq!(notify.try_recv(), Some(HostNotifyEvent { address: 0x2c, data: 0x1234 }));
+    /// assert_eq!(notify.try_recv(), None);
+    /// ```
+    pub fn forward<S: FrameSource>(&self, source: &mut S) -> io::Result<usize> {
+        let mut count = 0;
+        while let Some(frame) = source.next_frame()? {
+            if let Some((&address, message)) = frame.split_first() {
+                if address >> 1 == SMBUS_HOST_ADDRESS as u8 {
+                    match self.notify_raw(message) {
+                        Ok(()) => count += 1,
+                        Err(ref e) if e.kind() == io::ErrorKind::InvalidData => (),
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+}
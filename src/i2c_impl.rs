@@ -1,9 +1,11 @@
 use {
-    super::{I2c, ReadFlags, ReadWrite, WriteFlags},
+    super::{transmute_slice_mut, I2c, Message, ReadFlags, ReadWrite, WriteFlags},
     i2c::{ReadFlags as I2cReadFlags, WriteFlags as I2cWriteFlags},
-    i2c_linux_sys::{i2c_msg, i2c_rdwr, Flags, I2C_RDWR_IOCTL_MAX_MSGS},
-    resize_slice::ResizeSlice,
-    std::{io, mem::MaybeUninit, os::unix::io::AsRawFd},
+    std::{
+        io,
+        mem::{self, MaybeUninit},
+        os::unix::io::AsRawFd,
+    },
 };
 
 impl<I: AsRawFd> i2c::Master for I2c<I> {
@@ -80,53 +82,91 @@ impl<I: AsRawFd> i2c::BlockTransfer for I2c<I> {
     }
 }
 
+/// `RECEIVE_LEN` reads take a buffer of at least 33 bytes, and are truncated
+/// to the count byte followed by the block it announces.
+///
+/// ```rust,no_run
+/// use {
+///     i2c::{BulkTransfer, Message, ReadFlags, WriteFlags},
+///     i2c_linux::I2c,
+/// };
+///
+/// # fn main_res() -> ::std::io::Result<()> {
+/// let mut i2c = I2c::from_path("/dev/i2c-1")?;
+/// let mut block = [0u8; 33];
+/// let mut messages = [
+///     Message::Write {
+///         address: 0x2c,
+///         data: &[0x10],
+///         flags: WriteFlags::default(),
+///     },
+///     Message::Read {
+///         address: 0x2c,
+///         data: &mut block,
+///         flags: ReadFlags::RECEIVE_LEN,
+///     },
+/// ];
+/// BulkTransfer::i2c_transfer(&mut i2c, &mut messages)?;
+/// if let Message::Read { ref data, .. } = messages[1] {
+///     let (&count, block) = data.split_first().unwrap();
+///     assert_eq!(count as usize, block.len());
+/// }
+/// # Ok(())
+/// # }
+/// # fn main() { main_res().unwrap() }
+/// ```
 impl<I: AsRawFd> i2c::BulkTransfer for I2c<I> {
     fn i2c_transfer_support(&mut self) -> Result<(i2c::ReadFlags, i2c::WriteFlags), Self::Error> {
         I2c::i2c_transfer_flags(self).map(|(read, write)| (read.into(), write.into()))
     }
 
     fn i2c_transfer(&mut self, messages: &mut [i2c::Message]) -> Result<(), Self::Error> {
-        let mut message_buffer = [MaybeUninit::<i2c_msg>::uninit(); I2C_RDWR_IOCTL_MAX_MSGS];
-        assert!(messages.len() <= message_buffer.len());
+        if messages.len() > i2c_linux_sys::I2C_RDWR_IOCTL_MAX_MSGS {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many I2C messages"))
+        }
 
-        for (out, msg) in message_buffer.iter_mut().zip(messages.iter_mut()) {
+        // Read buffers are moved into the converted messages, then moved back
+        // once the transfer has truncated them
+        let mut buffer: [MaybeUninit<Message>; i2c_linux_sys::I2C_RDWR_IOCTL_MAX_MSGS] =
+            unsafe { MaybeUninit::uninit().assume_init() };
+        for (out, msg) in buffer.iter_mut().zip(messages.iter_mut()) {
             out.write(match *msg {
                 i2c::Message::Read {
                     address,
                     ref mut data,
                     flags,
-                } => i2c_msg {
-                    addr: address,
-                    flags: Flags::from_bits_truncate(ReadFlags::from(flags).bits()) | Flags::RD,
-                    len: data.len() as _,
-                    buf: data.as_mut_ptr(),
+                } => {
+                    let data = mem::take(data);
+                    if flags.contains(I2cReadFlags::RECEIVE_LEN) {
+                        // The kernel expects the initial length in the first
+                        // byte, which the count byte then replaces
+                        if let Some(initial) = data.first_mut() {
+                            *initial = 1;
+                        }
+                    }
+                    Message::Read {
+                        address,
+                        data,
+                        flags: flags.into(),
+                    }
                 },
-                i2c::Message::Write {
+                i2c::Message::Write { address, data, flags } => Message::Write {
                     address,
-                    ref data,
-                    flags,
-                } => i2c_msg {
-                    addr: address,
-                    flags: Flags::from_bits_truncate(WriteFlags::from(flags).bits()),
-                    len: data.len() as _,
-                    buf: data.as_ptr() as *mut _,
+                    data,
+                    flags: flags.into(),
                 },
             });
         }
-        let messages_raw = unsafe { crate::transmute_slice_mut(&mut message_buffer[..messages.len()]) };
-
-        let res = unsafe {
-            i2c_rdwr(self.as_raw_fd(), messages_raw)?;
-        };
+        let converted: &mut [Message] = unsafe { transmute_slice_mut(&mut buffer[..messages.len()]) };
+        let res = I2c::i2c_transfer(self, converted);
 
-        for (msg, out) in messages_raw.iter().zip(messages.iter_mut()) {
-            match *out {
-                i2c::Message::Read { ref mut data, .. } => data.resize_to(msg.len as usize),
-                i2c::Message::Write { .. } => (),
+        for (msg, converted) in messages.iter_mut().zip(converted.iter_mut()) {
+            if let (i2c::Message::Read { data, .. }, Message::Read { data: read, .. }) = (msg, converted) {
+                *data = mem::take(read);
             }
         }
 
-        Ok(res)
+        res
     }
 }
 
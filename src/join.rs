@@ -0,0 +1,41 @@
+// Joins a command byte and its data into one write buffer, for adapters that
+// cannot continue a message without a repeated START. This only depends on
+// std so that benches/write_block_data.rs can include it directly.
+
+use std::io;
+
+// Longest joined buffer kept on the stack
+pub(crate) const WRITE_BUFFER_LEN: usize = 256;
+
+// Calls `f` with `command` followed by `value`, joined in `scratch` if given,
+// or otherwise on the stack when short enough and on the heap when not.
+pub(crate) fn join_command<R, F: FnOnce(&[u8]) -> io::Result<R>>(
+    command: u8,
+    value: &[u8],
+    scratch: Option<&mut [u8]>,
+    f: F,
+) -> io::Result<R> {
+    match scratch {
+        Some(scratch) => {
+            let data = scratch
+                .get_mut(..value.len() + 1)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "scratch buffer too short"))?;
+            data[0] = command;
+            data[1..].copy_from_slice(value);
+            f(data)
+        },
+        None if value.len() < WRITE_BUFFER_LEN => {
+            let mut buffer = [0u8; WRITE_BUFFER_LEN];
+            let data = &mut buffer[..value.len() + 1];
+            data[0] = command;
+            data[1..].copy_from_slice(value);
+            f(data)
+        },
+        None => {
+            let mut data = Vec::with_capacity(value.len() + 1);
+            data.push(command);
+            data.extend_from_slice(value);
+            f(&data)
+        },
+    }
+}
@@ -23,17 +23,19 @@
 //!
 //! - `i2c` will impl [i2c](https://crates.io/crates/i2c) traits for `I2c`.
 //! - `udev` must be enabled to use `Enumerator`.
+//! - `embedded-hal` will impl [embedded-hal](https://crates.io/crates/embedded-hal) digital traits
+//!   for `expander::Pin`.
 
 pub use i2c_linux_sys::{Functionality, SmbusReadWrite as ReadWrite};
 use {
     bitflags::bitflags,
     i2c_linux_sys as i2c,
     resize_slice::ResizeSlice,
+    retry::{RetryPolicy, RetryStats},
     std::{
         cmp,
         fs::{File, OpenOptions},
         io::{self, Read, Write},
-        iter,
         mem::{transmute, MaybeUninit},
         os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
         path::Path,
@@ -53,6 +55,30 @@ pub use enumerate::Enumerator;
 #[cfg_attr(feature = "doc", doc(cfg(feature = "i2c")))]
 mod i2c_impl;
 
+mod emulation;
+mod join;
+
+pub mod alert;
+pub mod arp;
+pub mod ddc;
+pub mod expander;
+pub mod functionality;
+pub mod hid;
+pub mod host_notify;
+pub mod lock;
+pub mod mctp;
+pub mod mux;
+pub mod pmbus;
+pub mod retry;
+pub mod sbs;
+pub mod sensors;
+pub mod shared;
+pub mod spd;
+pub mod ssif;
+pub mod transaction;
+
+pub use shared::{BusDevice, SharedBus};
+
 /// Part of a combined I2C transaction.
 pub enum Message<'a> {
     /// I2C read command
@@ -73,6 +99,23 @@ pub enum Message<'a> {
         /// Additional flags can modify the operation to work around device quirks.
         flags: WriteFlags,
     },
+    /// SMBus-style block read, where the device sends the block length
+    /// before the data.
+    ///
+    /// `data` must hold at least `I2C_SMBUS_BLOCK_MAX + 1` bytes, plus one
+    /// more with `pec`, and is truncated to the block itself on completion.
+    BlockRead {
+        /// The slave address of the device to read from.
+        address: u16,
+        /// A data buffer to read into.
+        data: &'a mut [u8],
+        /// Additional flags can modify the operation to work around device quirks.
+        /// `RECEIVE_LEN` is implied.
+        flags: ReadFlags,
+        /// Whether the device appends a Packet Error Code to verify, which
+        /// also covers a write to the same device just before this message.
+        pec: bool,
+    },
 }
 
 impl<'a> Message<'a> {
@@ -81,6 +124,7 @@ impl<'a> Message<'a> {
         match *self {
             Message::Read { ref data, .. } => data.len(),
             Message::Write { ref data, .. } => data.len(),
+            Message::BlockRead { ref data, .. } => data.len(),
         }
     }
 
@@ -89,6 +133,7 @@ impl<'a> Message<'a> {
         match *self {
             Message::Read { address, .. } => address,
             Message::Write { address, .. } => address,
+            Message::BlockRead { address, .. } => address,
         }
     }
 }
@@ -100,6 +145,13 @@ bitflags! {
         /// This is a 10-bit chip address.
         const TENBIT_ADDR = i2c::I2C_M_TEN;
         /// The first received byte will indicate the remaining length of the transfer.
+        ///
+        /// As with the kernel interface, the first byte of the buffer must
+        /// hold the number of bytes to read besides the block itself (1, or 2
+        /// with PEC), and the buffer must have room for that many bytes plus
+        /// `I2C_SMBUS_BLOCK_MAX`. The read is truncated to the received
+        /// length, including the length byte. `Message::BlockRead` takes care
+        /// of all of this.
         const RECEIVE_LEN = i2c::I2C_M_RECV_LEN;
         /// NACK bit is generated for this read.
         ///
@@ -154,11 +206,21 @@ bitflags! {
 }
 
 /// A safe wrapper around an I2C device.
+///
+/// SMBus commands that the adapter does not support are emulated with
+/// `i2c_transfer()` when it supports plain I2C transfers, including Packet
+/// Error Checking.
 pub struct I2c<I> {
     inner: I,
     address: Option<u16>,
     address_10bit: bool,
     functionality: Option<Functionality>,
+    retry_policy: Option<RetryPolicy>,
+    retry_stats: RetryStats,
+    retries: Option<usize>,
+    timeout: Option<Duration>,
+    pec: bool,
+    byte_block_emulation: bool,
 }
 
 impl I2c<File> {
@@ -176,6 +238,12 @@ impl<I> I2c<I> {
             address: None,
             address_10bit: false,
             functionality: None,
+            retry_policy: None,
+            retry_stats: RetryStats::default(),
+            retries: None,
+            timeout: None,
+            pec: false,
+            byte_block_emulation: false,
         }
     }
 
@@ -215,33 +283,55 @@ impl FromRawFd for I2c<File> {
 
 // TODO: add assertions for block lengths, return a proper io::Error
 impl<I: AsRawFd> I2c<I> {
-    fn update_functionality(&mut self) -> Option<Functionality> {
-        if let Some(func) = self.functionality.clone() {
-            Some(func)
-        } else {
-            let functionality = self.i2c_functionality().ok();
-            self.functionality = functionality.clone();
-            functionality
+    /// Sets the number of times the adapter retries communication before
+    /// failing.
+    ///
+    /// Fails with `InvalidInput` if `value` exceeds what the kernel accepts.
+    pub fn i2c_set_retries(&mut self, value: usize) -> io::Result<()> {
+        if value > i32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "I2C retry count out of range",
+            ))
         }
+
+        i2c::i2c_set_retries(self.as_raw_fd(), value)?;
+        self.retries = Some(value);
+        Ok(())
     }
 
-    /// Sets the number of times to retry communication before failing.
-    pub fn i2c_set_retries(&self, value: usize) -> io::Result<()> {
-        i2c::i2c_set_retries(self.as_raw_fd(), value)
+    /// The retry count last set through this handle, or `None` if the
+    /// adapter still uses its default.
+    pub fn i2c_retries(&self) -> Option<usize> {
+        self.retries
+    }
+
+    /// Sets a timeout for I2C operations.
+    ///
+    /// The kernel counts the timeout in units of 10 milliseconds, so
+    /// `duration` is rounded up to the next multiple of 10 ms. Fails with
+    /// `InvalidInput` if `duration` is zero or exceeds what the kernel
+    /// accepts.
+    pub fn i2c_set_timeout(&mut self, duration: Duration) -> io::Result<()> {
+        let ms = timeout_ms(duration)?;
+        i2c::i2c_set_timeout_ms(self.as_raw_fd(), ms)?;
+        self.timeout = Some(Duration::from_millis(ms as u64));
+        Ok(())
     }
 
-    /// Sets a timeout for I2C operations
-    pub fn i2c_set_timeout(&self, duration: Duration) -> io::Result<()> {
-        let value = duration.as_secs() as usize * 1000 + duration.subsec_nanos() as usize / 1000000;
-        i2c::i2c_set_timeout_ms(self.as_raw_fd(), value as _)
+    /// The timeout last set through this handle, as rounded for the kernel,
+    /// or `None` if the adapter still uses its default.
+    pub fn i2c_timeout(&self) -> Option<Duration> {
+        self.timeout
     }
 
     /// Set the slave address to communicate with.
     pub fn smbus_set_slave_address(&mut self, address: u16, tenbit: bool) -> io::Result<()> {
-        if let Some(func) = self.update_functionality() {
-            if func.contains(Functionality::TENBIT_ADDR) || tenbit {
-                i2c::i2c_set_slave_address_10bit(self.as_raw_fd(), tenbit)?;
-            }
+        if tenbit {
+            self.require(Functionality::TENBIT_ADDR)?;
+        }
+        if self.functionality()?.contains(Functionality::TENBIT_ADDR) {
+            i2c::i2c_set_slave_address_10bit(self.as_raw_fd(), tenbit)?;
         }
 
         let res = i2c::i2c_set_slave_address(self.as_raw_fd(), address, false);
@@ -255,12 +345,36 @@ impl<I: AsRawFd> I2c<I> {
     }
 
     /// Enable or disable SMBus Packet Error Checking.
-    pub fn smbus_set_pec(&self, pec: bool) -> io::Result<()> {
-        i2c::i2c_pec(self.as_raw_fd(), pec)
+    pub fn smbus_set_pec(&mut self, pec: bool) -> io::Result<()> {
+        i2c::i2c_pec(self.as_raw_fd(), pec)?;
+        self.pec = pec;
+        Ok(())
+    }
+
+    /// Allow `i2c_read_block_data` and `i2c_write_block_data` to fall back
+    /// to one `smbus_read_byte_data` or `smbus_write_byte_data` per byte, on
+    /// adapters that support neither I2C transfers nor I2C block commands.
+    ///
+    /// This is disabled by default, as it is only correct for devices that
+    /// expose one register per byte: byte `n` of the block is transferred to
+    /// or from register `command + n` in a separate bus transaction, so a
+    /// multi-byte value may be torn by concurrent updates, and a failure may
+    /// leave a write partially done.
+    pub fn set_byte_block_emulation(&mut self, enable: bool) {
+        self.byte_block_emulation = enable;
+    }
+
+    /// Whether block commands may be emulated one byte at a time, see
+    /// `set_byte_block_emulation()`.
+    pub fn byte_block_emulation(&self) -> bool {
+        self.byte_block_emulation
     }
 
     /// Retrieve the capabilities of the I2C device. These should be checked
     /// before attempting to use certain SMBus commands or I2C flags.
+    ///
+    /// This queries the adapter every time, see `functionality()` for a
+    /// cached version.
     pub fn i2c_functionality(&self) -> io::Result<Functionality> {
         i2c::i2c_get_functionality(self.as_raw_fd())
     }
@@ -293,10 +407,33 @@ impl<I: AsRawFd> I2c<I> {
     /// Data buffers are truncated to the actual read length on completion.
     ///
     /// See the `I2C_RDWR` ioctl for more information.
+    ///
+    /// Adapters without `Functionality::I2C` can only perform transfers that
+    /// match an SMBus command they support: a single write of up to 33
+    /// bytes, a single read of up to 1 byte, or a 1-byte register write
+    /// followed by a read of up to 32 bytes or a block read.
+    /// Other transfers fail with `Unsupported`. The slave address and PEC
+    /// setting are switched for the command and restored afterwards.
     pub fn i2c_transfer(&mut self, messages: &mut [Message]) -> io::Result<()> {
+        if !self.supports_i2c_transfer()? {
+            return self.emulated_i2c_transfer(messages)
+        }
+
         let mut message_buffer = [MaybeUninit::<i2c::i2c_msg>::uninit(); i2c::I2C_RDWR_IOCTL_MAX_MSGS];
         assert!(messages.len() <= message_buffer.len());
 
+        // The initial length of each RECEIVE_LEN read, which the kernel
+        // overwrites with the received length
+        let mut initial = [0u8; i2c::I2C_RDWR_IOCTL_MAX_MSGS];
+        for (msg, initial) in messages.iter_mut().zip(initial.iter_mut()) {
+            match *msg {
+                Message::Read { ref data, flags, .. } if flags.contains(ReadFlags::RECEIVE_LEN) =>
+                    *initial = receive_len_initial(data, data.first().cloned().unwrap_or(0))?,
+                Message::BlockRead { ref data, pec, .. } => *initial = receive_len_initial(data, 1 + pec as u8)?,
+                _ => (),
+            }
+        }
+
         for (out, msg) in message_buffer.iter_mut().zip(messages.iter_mut()) {
             out.write(match *msg {
                 Message::Read {
@@ -319,27 +456,58 @@ impl<I: AsRawFd> I2c<I> {
                     len: data.len() as _,
                     buf: data.as_ptr() as *mut _,
                 },
+                Message::BlockRead {
+                    address,
+                    ref mut data,
+                    flags,
+                    ..
+                } => i2c::i2c_msg {
+                    addr: address,
+                    flags: i2c::Flags::from_bits_truncate((flags | ReadFlags::RECEIVE_LEN).bits()) | i2c::Flags::RD,
+                    len: data.len() as _,
+                    buf: data.as_mut_ptr(),
+                },
             });
         }
         let messages_raw: &mut [i2c::i2c_msg] = unsafe { transmute_slice_mut(&mut message_buffer[..messages.len()]) };
 
-        let res = unsafe {
-            i2c::i2c_rdwr(self.as_raw_fd(), messages_raw)?;
-        };
-
-        for (msg, out) in messages_raw.iter().zip(messages.iter_mut()) {
-            match out {
-                Message::Read { ref mut data, .. } => data.resize_to(msg.len as usize),
-                Message::Write { .. } => (),
+        self.retry(|fd| {
+            for (msg, &initial) in messages_raw.iter().zip(initial.iter()) {
+                if initial != 0 {
+                    unsafe { *msg.buf = initial };
+                }
+            }
+            unsafe { i2c::i2c_rdwr(fd, &mut messages_raw[..]) }
+        })?;
+
+        for i in 0..messages.len() {
+            let crc = match messages[i] {
+                Message::BlockRead { address, pec: true, .. } =>
+                    Some(block_read_pec(address, i.checked_sub(1).map(|i| &messages[i]))),
+                _ => None,
+            };
+            match messages[i] {
+                Message::Read {
+                    ref mut data, flags, ..
+                } if flags.contains(ReadFlags::RECEIVE_LEN) => {
+                    let len = cmp::min(initial[i] as usize + data[0] as usize, data.len());
+                    data.resize_to(len)
+                },
+                Message::Read { .. } | Message::Write { .. } => (),
+                Message::BlockRead { ref mut data, .. } => finish_block_read(data, crc)?,
             }
         }
 
-        Ok(res)
+        Ok(())
     }
 
     /// Sends a single bit to the device, in the place of the Rd/Wr address bit.
     pub fn smbus_write_quick(&mut self, value: ReadWrite) -> io::Result<()> {
-        i2c::i2c_smbus_write_quick(self.as_raw_fd(), value)
+        if self.emulate_smbus(Functionality::SMBUS_QUICK)? {
+            return self.emulated_write_quick(matches!(value, ReadWrite::Read))
+        }
+
+        self.retry(|fd| i2c::i2c_smbus_write_quick(fd, value))
     }
 
     /// Reads a single byte from a device without specifying a register.
@@ -348,51 +516,105 @@ impl<I: AsRawFd> I2c<I> {
     /// is a shorthand if you want to read the same register as in the previous
     /// SMBus command.
     pub fn smbus_read_byte(&mut self) -> io::Result<u8> {
-        i2c::i2c_smbus_read_byte(self.as_raw_fd())
+        if self.emulate_smbus(Functionality::SMBUS_READ_BYTE)? {
+            let mut value = [0];
+            return self.emulated_transfer(&[], &mut value).map(|()| value[0])
+        }
+
+        self.retry(i2c::i2c_smbus_read_byte)
     }
 
     /// Sends a single byte to a device.
     pub fn smbus_write_byte(&mut self, value: u8) -> io::Result<()> {
-        i2c::i2c_smbus_write_byte(self.as_raw_fd(), value)
+        if self.emulate_smbus(Functionality::SMBUS_WRITE_BYTE)? {
+            return self.emulated_transfer(&[value], &mut [])
+        }
+
+        self.retry(|fd| i2c::i2c_smbus_write_byte(fd, value))
     }
 
     /// Reads a single byte from a device from the designated register.
     pub fn smbus_read_byte_data(&mut self, command: u8) -> io::Result<u8> {
-        i2c::i2c_smbus_read_byte_data(self.as_raw_fd(), command)
+        if self.emulate_smbus(Functionality::SMBUS_READ_BYTE_DATA)? {
+            let mut value = [0];
+            return self.emulated_transfer(&[command], &mut value).map(|()| value[0])
+        }
+
+        self.retry(|fd| i2c::i2c_smbus_read_byte_data(fd, command))
     }
 
     /// Writes a single byte to a device to the designated register.
     pub fn smbus_write_byte_data(&mut self, command: u8, value: u8) -> io::Result<()> {
-        i2c::i2c_smbus_write_byte_data(self.as_raw_fd(), command, value)
+        if self.emulate_smbus(Functionality::SMBUS_WRITE_BYTE_DATA)? {
+            return self.emulated_transfer(&[command, value], &mut [])
+        }
+
+        self.retry(|fd| i2c::i2c_smbus_write_byte_data(fd, command, value))
     }
 
     /// Reads a 16-bit word from the device register.
     pub fn smbus_read_word_data(&mut self, command: u8) -> io::Result<u16> {
-        i2c::i2c_smbus_read_word_data(self.as_raw_fd(), command)
+        if self.emulate_smbus(Functionality::SMBUS_READ_WORD_DATA)? {
+            let mut value = [0; 2];
+            return self
+                .emulated_transfer(&[command], &mut value)
+                .map(|()| u16::from_le_bytes(value))
+        }
+
+        self.retry(|fd| i2c::i2c_smbus_read_word_data(fd, command))
     }
 
     /// Writes a 16-bit word to the device register.
     pub fn smbus_write_word_data(&mut self, command: u8, value: u16) -> io::Result<()> {
-        i2c::i2c_smbus_write_word_data(self.as_raw_fd(), command, value)
+        if self.emulate_smbus(Functionality::SMBUS_WRITE_WORD_DATA)? {
+            let [low, high] = value.to_le_bytes();
+            return self.emulated_transfer(&[command, low, high], &mut [])
+        }
+
+        self.retry(|fd| i2c::i2c_smbus_write_word_data(fd, command, value))
     }
 
     /// Selects a device register, sends a 16-bit word to it, and read 16-bits
     /// of data in return.
     pub fn smbus_process_call(&mut self, command: u8, value: u16) -> io::Result<u16> {
-        i2c::i2c_smbus_process_call(self.as_raw_fd(), command, value)
+        if self.emulate_smbus(Functionality::SMBUS_PROC_CALL)? {
+            let [low, high] = value.to_le_bytes();
+            let mut value = [0; 2];
+            return self
+                .emulated_transfer(&[command, low, high], &mut value)
+                .map(|()| u16::from_le_bytes(value))
+        }
+
+        self.retry(|fd| i2c::i2c_smbus_process_call(fd, command, value))
     }
 
     /// Read up to 32 bytes from the designated device register.
     ///
     /// Returns the amount of data read.
     pub fn smbus_read_block_data(&mut self, command: u8, value: &mut [u8]) -> io::Result<usize> {
+        if self.emulate_smbus(Functionality::SMBUS_READ_BLOCK_DATA)? {
+            return self.emulated_block_transfer(&[command], value)
+        }
+
         let len = cmp::min(value.len(), i2c::I2C_SMBUS_BLOCK_MAX);
-        i2c::i2c_smbus_read_block_data(self.as_raw_fd(), command, &mut value[..len])
+        self.retry(|fd| i2c::i2c_smbus_read_block_data(fd, command, &mut value[..len]))
     }
 
     /// Write up to 32 bytes to the designated device register.
     pub fn smbus_write_block_data(&mut self, command: u8, value: &[u8]) -> io::Result<()> {
-        i2c::i2c_smbus_write_block_data(self.as_raw_fd(), command, value)
+        if self.emulate_smbus(Functionality::SMBUS_WRITE_BLOCK_DATA)? {
+            if value.len() > i2c::I2C_SMBUS_BLOCK_MAX {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "SMBus block too long"))
+            }
+
+            let mut buffer = [0u8; i2c::I2C_SMBUS_BLOCK_MAX + 2];
+            buffer[0] = command;
+            buffer[1] = value.len() as u8;
+            buffer[2..2 + value.len()].copy_from_slice(value);
+            return self.emulated_transfer(&buffer[..2 + value.len()], &mut [])
+        }
+
+        self.retry(|fd| i2c::i2c_smbus_write_block_data(fd, command, value))
     }
 
     /// Sends up to 31 bytes of data to the designated device register, and reads
@@ -400,8 +622,20 @@ impl<I: AsRawFd> I2c<I> {
     ///
     /// This was introduced in SMBus 2.0
     pub fn smbus_block_process_call(&mut self, command: u8, write: &[u8], read: &mut [u8]) -> io::Result<usize> {
+        if self.emulate_smbus(Functionality::SMBUS_BLOCK_PROC_CALL)? {
+            if write.len() > i2c::I2C_SMBUS_BLOCK_MAX {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "SMBus block too long"))
+            }
+
+            let mut buffer = [0u8; i2c::I2C_SMBUS_BLOCK_MAX + 2];
+            buffer[0] = command;
+            buffer[1] = write.len() as u8;
+            buffer[2..2 + write.len()].copy_from_slice(write);
+            return self.emulated_block_transfer(&buffer[..2 + write.len()], read)
+        }
+
         let read_len = cmp::min(read.len(), i2c::I2C_SMBUS_BLOCK_MAX);
-        i2c::i2c_smbus_block_process_call(self.as_raw_fd(), command, write, &mut read[..read_len])
+        self.retry(|fd| i2c::i2c_smbus_block_process_call(fd, command, write, &mut read[..read_len]))
     }
 
     /// Reads a block of bytes from the designated device register.
@@ -411,40 +645,51 @@ impl<I: AsRawFd> I2c<I> {
     /// `i2c_transfer()` if more data is needed. `write()`+`read()` may also be
     /// an option, though will produce an I2C STOP condition between the
     /// transfers, which may be undesirable.
+    ///
+    /// Adapters with neither I2C transfers nor I2C block reads fail with
+    /// `Unsupported`, unless `set_byte_block_emulation()` allows reading the
+    /// block one register at a time.
     pub fn i2c_read_block_data(&mut self, command: u8, value: &mut [u8]) -> io::Result<usize> {
         // Compatibility/emulation
-        if let Some(func) = self.update_functionality() {
-            if !func.contains(Functionality::SMBUS_READ_I2C_BLOCK) || value.len() > i2c::I2C_SMBUS_BLOCK_MAX {
-                if func.contains(Functionality::I2C) {
-                    if let Some(address) = self.address {
-                        let mut msgs = [
-                            Message::Write {
-                                address,
-                                data: &[command],
-                                flags: if self.address_10bit {
-                                    WriteFlags::TENBIT_ADDR
-                                } else {
-                                    WriteFlags::default()
-                                },
-                            },
-                            Message::Read {
-                                address,
-                                data: value,
-                                flags: if self.address_10bit {
-                                    ReadFlags::TENBIT_ADDR
-                                } else {
-                                    ReadFlags::default()
-                                },
-                            },
-                        ];
-                        return self.i2c_transfer(&mut msgs).map(|_| msgs[1].len())
-                    }
-                }
+        let func = self.functionality()?;
+        if (!func.contains(Functionality::SMBUS_READ_I2C_BLOCK) || value.len() > i2c::I2C_SMBUS_BLOCK_MAX)
+            && self.supports_i2c_transfer()?
+        {
+            if let Some(address) = self.address {
+                let mut msgs = [
+                    Message::Write {
+                        address,
+                        data: &[command],
+                        flags: if self.address_10bit {
+                            WriteFlags::TENBIT_ADDR
+                        } else {
+                            WriteFlags::default()
+                        },
+                    },
+                    Message::Read {
+                        address,
+                        data: value,
+                        flags: if self.address_10bit {
+                            ReadFlags::TENBIT_ADDR
+                        } else {
+                            ReadFlags::default()
+                        },
+                    },
+                ];
+                return self.i2c_transfer(&mut msgs).map(|_| msgs[1].len())
             }
         }
 
+        if self.byte_block_emulation
+            && !func.contains(Functionality::SMBUS_READ_I2C_BLOCK)
+            && func.contains(Functionality::SMBUS_READ_BYTE_DATA)
+        {
+            return self.emulated_read_i2c_block(command, value)
+        }
+
+        self.require(Functionality::SMBUS_READ_I2C_BLOCK)?;
         let len = cmp::min(value.len(), i2c::I2C_SMBUS_BLOCK_MAX);
-        i2c::i2c_smbus_read_i2c_block_data(self.as_raw_fd(), command, &mut value[..len])
+        self.retry(|fd| i2c::i2c_smbus_read_i2c_block_data(fd, command, &mut value[..len]))
     }
 
     /// Writes a block of bytes from the designated device register.
@@ -452,45 +697,72 @@ impl<I: AsRawFd> I2c<I> {
     /// Unlike smbus_write_block_data this does not transfer the data length.
     /// This is limited to 32 bytes due to the use of the Linux SMBus interface.
     /// Use `i2c_transfer()` or `write()` instead if more data is needed.
+    ///
+    /// Adapters with neither I2C transfers nor I2C block writes fail with
+    /// `Unsupported`, unless `set_byte_block_emulation()` allows writing the
+    /// block one register at a time.
+    ///
+    /// Adapters without `Functionality::NO_START` need the command and data
+    /// joined into one buffer, which is kept on the stack for up to 255 bytes
+    /// of data. See `i2c_write_block_data_with()` to avoid allocating for
+    /// longer writes.
     pub fn i2c_write_block_data(&mut self, command: u8, value: &[u8]) -> io::Result<()> {
+        self.write_i2c_block_data(command, value, None)
+    }
+
+    /// Like `i2c_write_block_data()`, but uses `scratch` to join the command
+    /// and data when needed.
+    ///
+    /// Fails with `InvalidInput` if the command and data must be joined and
+    /// `scratch` is shorter than `value.len() + 1` bytes.
+    pub fn i2c_write_block_data_with(&mut self, command: u8, value: &[u8], scratch: &mut [u8]) -> io::Result<()> {
+        self.write_i2c_block_data(command, value, Some(scratch))
+    }
+
+    fn write_i2c_block_data(&mut self, command: u8, value: &[u8], scratch: Option<&mut [u8]>) -> io::Result<()> {
         // Compatibility/emulation
-        if let Some(func) = self.update_functionality() {
-            if !func.contains(Functionality::SMBUS_WRITE_I2C_BLOCK) || value.len() > i2c::I2C_SMBUS_BLOCK_MAX {
-                if func.contains(Functionality::I2C) {
-                    if let Some(address) = self.address {
-                        let flags = if self.address_10bit {
-                            WriteFlags::TENBIT_ADDR
-                        } else {
-                            WriteFlags::default()
-                        };
-                        return if func.contains(Functionality::NO_START) {
-                            self.i2c_transfer(&mut [
-                                Message::Write {
-                                    address,
-                                    data: &[command],
-                                    flags,
-                                },
-                                Message::Write {
-                                    address,
-                                    data: value,
-                                    flags: flags | WriteFlags::NO_START,
-                                },
-                            ])
-                        } else {
-                            self.i2c_transfer(&mut [Message::Write {
-                                address,
-                                data: &iter::once(command).chain(value.iter().cloned()).collect::<Vec<_>>(),
-                                flags,
-                            }])
-                        }
-                    } else {
-                        // could also just use i2c_transfer, not much difference
-                    }
+        let func = self.functionality()?;
+        if (!func.contains(Functionality::SMBUS_WRITE_I2C_BLOCK) || value.len() > i2c::I2C_SMBUS_BLOCK_MAX)
+            && self.supports_i2c_transfer()?
+        {
+            if let Some(address) = self.address {
+                let flags = if self.address_10bit {
+                    WriteFlags::TENBIT_ADDR
+                } else {
+                    WriteFlags::default()
+                };
+                return if func.contains(Functionality::NO_START) {
+                    self.i2c_transfer(&mut [
+                        Message::Write {
+                            address,
+                            data: &[command],
+                            flags,
+                        },
+                        Message::Write {
+                            address,
+                            data: value,
+                            flags: flags | WriteFlags::NO_START,
+                        },
+                    ])
+                } else {
+                    join::join_command(command, value, scratch, |data| {
+                        self.i2c_transfer(&mut [Message::Write { address, data, flags }])
+                    })
                 }
+            } else {
+                // could also just use i2c_transfer, not much difference
             }
         }
 
-        i2c::i2c_smbus_write_i2c_block_data(self.as_raw_fd(), command, value)
+        if self.byte_block_emulation
+            && !func.contains(Functionality::SMBUS_WRITE_I2C_BLOCK)
+            && func.contains(Functionality::SMBUS_WRITE_BYTE_DATA)
+        {
+            return self.emulated_write_i2c_block(command, value)
+        }
+
+        self.require(Functionality::SMBUS_WRITE_I2C_BLOCK)?;
+        self.retry(|fd| i2c::i2c_smbus_write_i2c_block_data(fd, command, value))
     }
 }
 
@@ -513,3 +785,101 @@ impl<I: Write> Write for I2c<I> {
 unsafe fn transmute_slice_mut<'a, R, T>(s: &'a mut [T]) -> &'a mut [R] {
     transmute(s)
 }
+
+// The kernel's `I2C_TIMEOUT` unit
+const TIMEOUT_UNIT_MS: u128 = 10;
+
+fn timeout_ms(duration: Duration) -> io::Result<usize> {
+    let units = duration.as_nanos().div_ceil(TIMEOUT_UNIT_MS * 1_000_000);
+    if units == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "I2C timeout must not be zero",
+        ))
+    }
+    if units > i32::MAX as u128 || units * TIMEOUT_UNIT_MS > usize::MAX as u128 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "I2C timeout out of range"))
+    }
+
+    Ok((units * TIMEOUT_UNIT_MS) as usize)
+}
+
+// Checks a RECEIVE_LEN buffer, returning its initial length
+fn receive_len_initial(data: &[u8], initial: u8) -> io::Result<u8> {
+    if initial == 0 || data.len() < initial as usize + i2c::I2C_SMBUS_BLOCK_MAX {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "RECEIVE_LEN buffer too short for an SMBus block",
+        ))
+    }
+
+    Ok(initial)
+}
+
+// The PEC of a block read's address byte, and of a write to the same device
+// just before it
+fn block_read_pec(address: u16, previous: Option<&Message>) -> u8 {
+    let crc = match previous {
+        Some(&Message::Write {
+            address: write_address,
+            data,
+            ..
+        }) if write_address == address => smbus_pec_update(smbus_pec(&[(address << 1) as u8]), data),
+        _ => 0,
+    };
+    smbus_pec_update(crc, &[(address << 1) as u8 | 1])
+}
+
+// Strips the length byte from a completed block read, verifying its PEC if
+// given the PEC of everything before the length byte
+fn finish_block_read(data: &mut &mut [u8], crc: Option<u8>) -> io::Result<()> {
+    let count = data[0] as usize;
+    if count == 0 || count > i2c::I2C_SMBUS_BLOCK_MAX {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid SMBus block length"))
+    }
+    if let Some(crc) = crc {
+        let crc = smbus_pec_update(crc, &data[..1 + count]);
+        if crc != data[1 + count] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "SMBus PEC mismatch"))
+        }
+    }
+
+    data.copy_within(1..1 + count, 0);
+    data.resize_to(count);
+    Ok(())
+}
+
+/// Whether an error indicates that no device acknowledged its address.
+///
+/// `EIO` is deliberately excluded, as adapters also use it for arbitration
+/// loss and other genuine bus errors.
+fn is_nack(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ENXIO) | Some(libc::EREMOTEIO))
+}
+
+/// Calculates the SMBus Packet Error Code (CRC-8, polynomial `x^8 + x^2 + x + 1`)
+/// of a message, including the address bytes.
+///
+/// ```rust
+/// // Write byte 0x55 to command 0x01 of the device at 0x2c
+/// assert_eq!(i2c_linux::smbus_pec(&[0x58, 0x01, 0x55]), 0xcc);
+/// ```
+pub fn smbus_pec(data: &[u8]) -> u8 {
+    smbus_pec_update(0, data)
+}
+
+// Continues a PEC calculation over another part of a message
+fn smbus_pec_update(crc: u8, data: &[u8]) -> u8 {
+    data.iter().fold(crc, |crc, &byte| {
+        (0..8).fold(
+            crc ^ byte,
+            |crc, _| {
+                if crc & 0x80 != 0 {
+                    (crc << 1) ^ 0x07
+                } else {
+                    crc << 1
+                }
+            },
+        )
+    })
+}
@@ -0,0 +1,250 @@
+//! Advisory locking of an adapter between cooperating processes.
+//!
+//! The kernel does not prevent other processes from using an adapter in the
+//! middle of a multi-step sequence, such as selecting a page and then
+//! reading from it. Processes that agree to take the same [BusLock] around
+//! such sequences will not interleave with each other. The lock is an
+//! `flock(2)` on either the adapter's device node or a separate lock file.
+//!
+//! Taking a lock again while it is already held through the same handle or
+//! lock file, such as a nested [with_lock](I2c::with_lock), succeeds
+//! immediately and keeps the lock until the outermost holder releases it.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use {
+//!     i2c_linux::{lock::BusLock, I2c},
+//!     std::time::Duration,
+//! };
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut i2c = I2c::from_path("/dev/i2c-1")?;
+//! let lock = BusLock::device().timeout(Some(Duration::from_secs(1)));
+//! let data = i2c.with_lock(&lock, |i2c| {
+//!     i2c.smbus_set_slave_address(0x50, false)?;
+//!     i2c.smbus_write_byte_data(0x7f, 0x01)?;
+//!     i2c.smbus_read_byte_data(0x00)
+//! })?;
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::I2c,
+    std::{
+        fs::{self, File, OpenOptions},
+        io,
+        mem::MaybeUninit,
+        os::unix::io::{AsRawFd, RawFd},
+        path::{Path, PathBuf},
+        sync::{Mutex, MutexGuard},
+        thread::sleep,
+        time::{Duration, Instant},
+    },
+};
+
+/// The directory that [BusLock::run_lock] creates lock files in.
+pub const RUN_LOCK_DIR: &str = "/run/lock";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// The number of holders of each locked file descriptor. `flock` succeeds
+// immediately on an open file that already holds the lock, so only the last
+// holder may unlock it.
+static HOLDERS: Mutex<Vec<(RawFd, usize)>> = Mutex::new(Vec::new());
+
+/// An advisory lock shared by cooperating processes.
+pub struct BusLock {
+    file: Option<(File, PathBuf)>,
+    timeout: Option<Duration>,
+}
+
+impl BusLock {
+    /// Locks the adapter's own device node.
+    pub fn device() -> Self {
+        BusLock {
+            file: None,
+            timeout: None,
+        }
+    }
+
+    /// Locks a lock file at `path`, creating it if necessary.
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        Ok(BusLock {
+            file: Some((file, path.to_owned())),
+            timeout: None,
+        })
+    }
+
+    /// Locks the lock file for adapter `i2c-N` under [RUN_LOCK_DIR].
+    pub fn run_lock(adapter: u32) -> io::Result<Self> {
+        Self::file(Path::new(RUN_LOCK_DIR).join(format!("i2c-{}.lock", adapter)))
+    }
+
+    /// Sets how long to wait for the lock, or `None` to wait indefinitely.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The path of the lock file, unless the device node is locked.
+    pub fn path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|(_, path)| path.as_path())
+    }
+
+    fn acquire(&self, fd: RawFd) -> io::Result<Held> {
+        let fd = match self.file {
+            Some((ref file, _)) => file.as_raw_fd(),
+            None => fd,
+        };
+
+        if let Some(held) = Held::nested(fd) {
+            return Ok(held)
+        }
+
+        let deadline = match self.timeout {
+            Some(timeout) => Instant::now() + timeout,
+            None => {
+                flock(fd, libc::LOCK_EX)?;
+                return Ok(Held::new(fd))
+            },
+        };
+        loop {
+            match flock(fd, libc::LOCK_EX | libc::LOCK_NB) {
+                Ok(()) => return Ok(Held::new(fd)),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock && Instant::now() < deadline =>
+                    sleep(POLL_INTERVAL),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    let message = match holder_pid(fd) {
+                        Some(pid) => format!("timed out waiting for i2c bus lock held by pid {}", pid),
+                        None => "timed out waiting for i2c bus lock".into(),
+                    };
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, message))
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The process currently holding the lock, if any, as reported by
+    /// `/proc/locks`.
+    ///
+    /// A device node lock can only be queried once it has been opened, so
+    /// this returns `None` for [BusLock::device].
+    pub fn holder(&self) -> Option<u32> {
+        self.file.as_ref().and_then(|(file, _)| holder_pid(file.as_raw_fd()))
+    }
+}
+
+impl<I: AsRawFd> I2c<I> {
+    /// Runs `f` while holding `lock`.
+    ///
+    /// Nesting is allowed: if `lock` is already held through this handle or
+    /// the same lock file, `f` runs immediately and the lock stays held until
+    /// the outer call returns.
+    ///
+    /// Fails with `TimedOut` if the lock is not acquired within its timeout,
+    /// naming the process that holds it when possible.
+    pub fn with_lock<R, F: FnOnce(&mut Self) -> io::Result<R>>(&mut self, lock: &BusLock, f: F) -> io::Result<R> {
+        let _held = lock.acquire(self.as_raw_fd())?;
+        f(self)
+    }
+
+    /// The process currently holding an advisory lock on this adapter's
+    /// device node, if any.
+    pub fn lock_holder(&self) -> Option<u32> {
+        holder_pid(self.as_raw_fd())
+    }
+}
+
+// Releases the lock when dropped, including while unwinding from `f`
+struct Held(RawFd);
+
+impl Held {
+    fn new(fd: RawFd) -> Self {
+        let mut holders = holders();
+        match holders.iter_mut().find(|(held, _)| *held == fd) {
+            Some((_, count)) => *count += 1,
+            None => holders.push((fd, 1)),
+        }
+        Held(fd)
+    }
+
+    // Joins a lock already held on `fd`
+    fn nested(fd: RawFd) -> Option<Self> {
+        let mut holders = holders();
+        let (_, count) = holders.iter_mut().find(|(held, _)| *held == fd)?;
+        *count += 1;
+        Some(Held(fd))
+    }
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        let mut holders = holders();
+        if let Some(i) = holders.iter().position(|&(held, _)| held == self.0) {
+            holders[i].1 -= 1;
+            if holders[i].1 == 0 {
+                holders.swap_remove(i);
+                let _ = flock(self.0, libc::LOCK_UN);
+            }
+        }
+    }
+}
+
+fn holders() -> MutexGuard<'static, Vec<(RawFd, usize)>> {
+    HOLDERS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn flock(fd: RawFd, operation: libc::c_int) -> io::Result<()> {
+    loop {
+        match unsafe { libc::flock(fd, operation) } {
+            0 => return Ok(()),
+            _ => match io::Error::last_os_error() {
+                ref e if e.kind() == io::ErrorKind::Interrupted => (),
+                e => return Err(e),
+            },
+        }
+    }
+}
+
+fn holder_pid(fd: RawFd) -> Option<u32> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    let stat = match unsafe { libc::fstat(fd, stat.as_mut_ptr()) } {
+        0 => unsafe { stat.assume_init() },
+        _ => return None,
+    };
+    // Only `unsafe` in older libc releases
+    #[allow(unused_unsafe)]
+    let (major, minor) = unsafe { (libc::major(stat.st_dev), libc::minor(stat.st_dev)) };
+
+    // Entries look like `1: FLOCK  ADVISORY  WRITE 1234 00:19:5678 0 EOF`,
+    // where the device numbers are hexadecimal. Waiters are marked with `->`.
+    let locks = fs::read_to_string("/proc/locks").ok()?;
+    locks.lines().find_map(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.get(1) != Some(&"FLOCK") {
+            return None
+        }
+
+        let mut id = fields.get(5)?.split(':');
+        let lock_major = u32::from_str_radix(id.next()?, 16).ok()?;
+        let lock_minor = u32::from_str_radix(id.next()?, 16).ok()?;
+        let lock_inode = id.next()?.parse::<u64>().ok()?;
+        if (lock_major, lock_minor, lock_inode) == (major, minor, stat.st_ino as u64) {
+            fields.get(4)?.parse().ok()
+        } else {
+            None
+        }
+    })
+}
@@ -0,0 +1,610 @@
+//! MCTP over SMBus framing, as described by DMTF DSP0237.
+//!
+//! Each MCTP packet is carried in an SMBus block write to the destination
+//! endpoint's slave address, using the MCTP command code followed by a byte
+//! count, the sender's slave address, the MCTP transport header, the packet
+//! payload and a PEC byte.
+//!
+//! Messages larger than the transmission unit are split into several packets
+//! by [fragment] and put back together by a [Reassembler]. Packets are sent
+//! with [Mctp], while incoming packets are read from any [FrameSource], such
+//! as the queue of a Linux slave backend ([SlaveMqueue]) or a textual bus
+//! capture ([Capture]).
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{mctp::{Mctp, Message, Reassembler, SlaveMqueue}, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let i2c = I2c::from_path("/dev/i2c-1")?;
+//! let mut mctp = Mctp::new(i2c, 0x10);
+//! mctp.send(0x1d, &Message {
+//!     dest_eid: 0x09,
+//!     source_eid: 0x08,
+//!     tag_owner: true,
+//!     tag: 0,
+//!     // MCTP control: Get Endpoint ID
+//!     body: vec![0x00, 0x80, 0x02],
+//! })?;
+//!
+//! let mut queue = SlaveMqueue::open("/sys/bus/i2c/devices/1-1010/slave-mqueue")?;
+//! let mut reassembler = Reassembler::new();
+//! loop {
+//!     match reassembler.receive(&mut queue)? {
+//!         Some(message) => break println!("{:?}", message),
+//!         None => {
+//!             queue.wait(None)?;
+//!         },
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{smbus_pec, I2c, Message as I2cMessage, WriteFlags},
+    std::{
+        cmp,
+        collections::HashMap,
+        fs::File,
+        io::{self, BufRead, Read, Seek, SeekFrom},
+        os::unix::io::{AsRawFd, RawFd},
+        path::Path,
+        time::Duration,
+    },
+};
+
+/// The SMBus command code that identifies MCTP packets.
+pub const MCTP_COMMAND_CODE: u8 = 0x0f;
+
+/// The MCTP transport header version supported by this module.
+pub const MCTP_HEADER_VERSION: u8 = 0x01;
+
+/// The baseline transmission unit, the largest payload that every endpoint
+/// must accept in a single packet.
+pub const BASELINE_TRANSMISSION_UNIT: usize = 64;
+
+/// The largest payload that fits in a single SMBus block write, after the
+/// source slave address and the transport header.
+pub const MAX_TRANSMISSION_UNIT: usize = 0xff - 1 - Header::LEN;
+
+// Bounds the memory used by a single message being reassembled
+const MAX_MESSAGE_LEN: usize = 0x10000;
+
+/// The MCTP transport header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Header {
+    /// The header version.
+    pub version: u8,
+    /// The destination endpoint ID.
+    pub dest_eid: u8,
+    /// The source endpoint ID.
+    pub source_eid: u8,
+    /// Start of message.
+    pub som: bool,
+    /// End of message.
+    pub eom: bool,
+    /// The packet sequence number, modulo 4.
+    pub seq: u8,
+    /// Whether the source endpoint originated the message tag.
+    pub tag_owner: bool,
+    /// The message tag.
+    pub tag: u8,
+}
+
+impl Header {
+    /// The encoded length of the header.
+    pub const LEN: usize = 4;
+
+    /// Decodes a transport header.
+    pub fn from_bytes(bytes: [u8; Self::LEN]) -> Self {
+        Header {
+            version: bytes[0] & 0x0f,
+            dest_eid: bytes[1],
+            source_eid: bytes[2],
+            som: bytes[3] & 0x80 != 0,
+            eom: bytes[3] & 0x40 != 0,
+            seq: (bytes[3] >> 4) & 0x03,
+            tag_owner: bytes[3] & 0x08 != 0,
+            tag: bytes[3] & 0x07,
+        }
+    }
+
+    /// Encodes the transport header.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        [
+            self.version & 0x0f,
+            self.dest_eid,
+            self.source_eid,
+            (self.som as u8) << 7
+                | (self.eom as u8) << 6
+                | (self.seq & 0x03) << 4
+                | (self.tag_owner as u8) << 3
+                | self.tag & 0x07,
+        ]
+    }
+}
+
+/// A single MCTP packet and its SMBus addressing.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Packet {
+    /// The 7-bit slave address of the receiving endpoint.
+    pub dest_address: u16,
+    /// The 7-bit slave address of the sending endpoint.
+    pub source_address: u16,
+    /// The transport header.
+    pub header: Header,
+    /// The packet payload.
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    /// Encodes the data of the SMBus block write carrying this packet, from
+    /// the command code through to the PEC.
+    ///
+    /// ```rust
+    /// use i2c_linux::mctp::{Header, Packet, MCTP_HEADER_VERSION};
+    ///
+    /// let packet = Packet {
+    ///     dest_address: 0x1d,
+    ///     source_address: 0x10,
+    ///     header: Header {
+    ///         version: MCTP_HEADER_VERSION,
+    ///         dest_eid: 0x09,
+    ///         source_eid: 0x08,
+    ///         som: true,
+    ///         eom: true,
+    ///         seq: 0,
+    ///         tag_owner: true,
+    ///         tag: 0,
+    ///     },
+    ///     payload: vec![0x00, 0x80, 0x02],
+    /// };
+    /// let data = packet.to_bytes();
+    /// assert_eq!(data, [0x0f, 0x08, 0x21, 0x01, 0x09, 0x08, 0xc8, 0x00, 0x80, 0x02, 0x30]);
+    /// assert_eq!(Packet::decode(0x1d, &data).unwrap(), packet);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4 + Header::LEN + self.payload.len());
+        data.push(MCTP_COMMAND_CODE);
+        data.push((1 + Header::LEN + self.payload.len()) as u8);
+        data.push((self.source_address as u8) << 1 | 1);
+        data.extend_from_slice(&self.header.to_bytes());
+        data.extend_from_slice(&self.payload);
+        data.push(pec(self.dest_address, &data));
+        data
+    }
+
+    /// Decodes the data of an SMBus block write received by the endpoint at
+    /// `dest_address`, verifying its byte count and PEC.
+    pub fn decode(dest_address: u16, data: &[u8]) -> io::Result<Self> {
+        let (&pec_byte, data) = data
+            .split_last()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "empty MCTP packet"))?;
+        match *data {
+            [MCTP_COMMAND_CODE, ..] => (),
+            [_, ..] => return Err(io::Error::new(io::ErrorKind::InvalidData, "not an MCTP packet")),
+            [] => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated MCTP packet")),
+        }
+        if data.len() < 3 + Header::LEN {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated MCTP packet"))
+        }
+        if data[1] as usize != data.len() - 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "MCTP packet byte count mismatch",
+            ))
+        }
+        if pec(dest_address, data) != pec_byte {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "MCTP packet PEC mismatch"))
+        }
+
+        let mut header = [0u8; Header::LEN];
+        header.copy_from_slice(&data[3..3 + Header::LEN]);
+        let header = Header::from_bytes(header);
+        if header.version != MCTP_HEADER_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported MCTP header version",
+            ))
+        }
+
+        Ok(Packet {
+            dest_address,
+            source_address: (data[2] >> 1) as u16,
+            header,
+            payload: data[3 + Header::LEN..].to_vec(),
+        })
+    }
+
+    /// Decodes a frame as captured on the bus or queued by a slave backend,
+    /// which begins with the destination address byte.
+    pub fn from_frame(frame: &[u8]) -> io::Result<Self> {
+        match frame.split_first() {
+            Some((&address, data)) => Self::decode((address >> 1) as u16, data),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "empty MCTP frame")),
+        }
+    }
+}
+
+fn pec(dest_address: u16, data: &[u8]) -> u8 {
+    // The PEC covers the address byte of the write, which is not part of the
+    // data itself
+    let address = [(dest_address as u8) << 1];
+    smbus_pec(&[&address[..], data].concat())
+}
+
+/// A complete MCTP message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Message {
+    /// The destination endpoint ID.
+    pub dest_eid: u8,
+    /// The source endpoint ID.
+    pub source_eid: u8,
+    /// Whether the source endpoint originated the message tag.
+    pub tag_owner: bool,
+    /// The message tag.
+    pub tag: u8,
+    /// The message body, starting with the message type byte.
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    /// The message type, without the integrity check bit.
+    pub fn message_type(&self) -> Option<u8> {
+        self.body.first().map(|&ty| ty & 0x7f)
+    }
+
+    /// Whether the message carries a message integrity check.
+    pub fn integrity_check(&self) -> bool {
+        self.body.first().map(|&ty| ty & 0x80 != 0).unwrap_or(false)
+    }
+}
+
+/// Splits a message into packets carrying at most `mtu` bytes of payload
+/// each, numbering them from `seq`.
+///
+/// `mtu` is clamped between [BASELINE_TRANSMISSION_UNIT] and
+/// [MAX_TRANSMISSION_UNIT].
+///
+/// ```rust
+/// use i2c_linux::mctp::{fragment, Message, Reassembler, BASELINE_TRANSMISSION_UNIT};
+///
+/// let message = Message {
+///     dest_eid: 0x09,
+///     source_eid: 0x08,
+///     tag_owner: true,
+///     tag: 3,
+///     body: (0..100).collect(),
+/// };
+/// let packets = fragment(&message, 0x1d, 0x10, BASELINE_TRANSMISSION_UNIT, 3);
+/// assert_eq!(packets.len(), 2);
+/// assert!(packets[0].header.som && !packets[0].header.eom);
+/// assert_eq!((packets[0].header.seq, packets[1].header.seq), (3, 0));
+/// assert_eq!(packets[1].payload.len(), 36);
+///
+/// let mut reassembler = Reassembler::new();
+/// assert_eq!(reassembler.push(&packets[0]).unwrap(), None);
+/// assert_eq!(reassembler.push(&packets[1]).unwrap(), Some(message));
+/// ```
+pub fn fragment(message: &Message, dest_address: u16, source_address: u16, mtu: usize, seq: u8) -> Vec<Packet> {
+    let mtu = mtu.clamp(BASELINE_TRANSMISSION_UNIT, MAX_TRANSMISSION_UNIT);
+    let count = cmp::max(1, message.body.len().div_ceil(mtu));
+    (0..count)
+        .map(|i| Packet {
+            dest_address,
+            source_address,
+            header: Header {
+                version: MCTP_HEADER_VERSION,
+                dest_eid: message.dest_eid,
+                source_eid: message.source_eid,
+                som: i == 0,
+                eom: i == count - 1,
+                seq: seq.wrapping_add(i as u8) & 0x03,
+                tag_owner: message.tag_owner,
+                tag: message.tag,
+            },
+            payload: message.body.iter().skip(i * mtu).take(mtu).cloned().collect(),
+        })
+        .collect()
+}
+
+struct Partial {
+    dest_eid: u8,
+    next_seq: u8,
+    body: Vec<u8>,
+}
+
+/// Reassembles messages from their packets.
+///
+/// Messages are tracked by their source endpoint, tag owner bit and tag, so
+/// packets of several messages may be interleaved.
+#[derive(Default)]
+pub struct Reassembler {
+    partial: HashMap<(u8, bool, u8), Partial>,
+}
+
+impl Reassembler {
+    /// Creates a reassembler with no messages in progress.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a packet, returning the message it completes.
+    ///
+    /// A packet that is out of sequence, or that continues a message whose
+    /// start was not seen, fails with `InvalidData` and discards the message
+    /// in progress.
+    pub fn push(&mut self, packet: &Packet) -> io::Result<Option<Message>> {
+        let header = &packet.header;
+        let key = (header.source_eid, header.tag_owner, header.tag);
+
+        let mut partial = if header.som {
+            Partial {
+                dest_eid: header.dest_eid,
+                next_seq: header.seq,
+                body: Vec::new(),
+            }
+        } else {
+            match self.partial.remove(&key) {
+                Some(partial) => partial,
+                None =>
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "MCTP packet does not continue a message",
+                    )),
+            }
+        };
+
+        if header.seq != partial.next_seq {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "MCTP packet out of sequence",
+            ))
+        }
+        if partial.body.len() + packet.payload.len() > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "MCTP message too long"))
+        }
+        partial.body.extend_from_slice(&packet.payload);
+        partial.next_seq = (partial.next_seq + 1) & 0x03;
+
+        if header.eom {
+            Ok(Some(Message {
+                dest_eid: partial.dest_eid,
+                source_eid: header.source_eid,
+                tag_owner: header.tag_owner,
+                tag: header.tag,
+                body: partial.body,
+            }))
+        } else {
+            self.partial.insert(key, partial);
+            Ok(None)
+        }
+    }
+
+    /// Reads frames from `source` until a message is complete.
+    ///
+    /// Frames that are not MCTP packets are ignored. Returns `None` once the
+    /// source has no more frames available.
+    pub fn receive<S: FrameSource + ?Sized>(&mut self, source: &mut S) -> io::Result<Option<Message>> {
+        while let Some(frame) = source.next_frame()? {
+            if frame.get(1) != Some(&MCTP_COMMAND_CODE) {
+                continue
+            }
+            if let Some(message) = self.push(&Packet::from_frame(&frame)?)? {
+                return Ok(Some(message))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Discards all messages in progress.
+    pub fn clear(&mut self) {
+        self.partial.clear()
+    }
+}
+
+/// A source of received SMBus frames, each beginning with the destination
+/// address byte.
+pub trait FrameSource {
+    /// Returns the next frame, or `None` if no frame is available.
+    fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// The message queue of a Linux `slave-mqueue` slave backend.
+///
+/// Each read of the queue's sysfs attribute returns a single frame.
+pub struct SlaveMqueue {
+    file: File,
+}
+
+impl SlaveMqueue {
+    /// Opens the `slave-mqueue` attribute at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        File::open(path).map(|file| SlaveMqueue { file })
+    }
+
+    /// Waits until a frame is queued, or `timeout` elapses.
+    ///
+    /// Returns `false` on timeout.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLPRI | libc::POLLERR,
+            revents: 0,
+        };
+        let timeout = timeout.map(|t| t.as_millis().min(libc::c_int::MAX as u128) as libc::c_int);
+        match unsafe { libc::poll(&mut fd, 1, timeout.unwrap_or(-1)) } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Ok(false),
+            _ => Ok(true),
+        }
+    }
+}
+
+impl FrameSource for SlaveMqueue {
+    fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut frame = vec![0u8; 0x100 + 2];
+        self.file.seek(SeekFrom::Start(0))?;
+        match self.file.read(&mut frame)? {
+            0 => Ok(None),
+            len => {
+                frame.truncate(len);
+                Ok(Some(frame))
+            },
+        }
+    }
+}
+
+impl AsRawFd for SlaveMqueue {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+/// Frames read from a textual bus capture.
+///
+/// Each line holds one frame as whitespace separated hexadecimal bytes, with
+/// an optional `0x` prefix. Blank lines and lines starting with `#` are
+/// skipped.
+///
+/// ```rust
+/// use i2c_linux::mctp::{Capture, Reassembler};
+///
+/// let capture = "# Get Endpoint ID request\n\
+///                3a 0f 08 21 01 09 08 c8 00 80 02 30\n";
+/// let message = Reassembler::new().receive(&mut Capture::new(capture.as_bytes())).unwrap().unwrap();
+/// assert_eq!(message.body, [0x00, 0x80, 0x02]);
+/// ```
+pub struct Capture<R> {
+    reader: R,
+    line: String,
+}
+
+impl<R: BufRead> Capture<R> {
+    /// Reads frames from `reader`.
+    pub fn new(reader: R) -> Self {
+        Capture {
+            reader,
+            line: String::new(),
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: BufRead> FrameSource for Capture<R> {
+    fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(None)
+            }
+
+            let line = self.line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+
+            return line
+                .split_whitespace()
+                .map(|byte| {
+                    let byte = byte.trim_start_matches("0x").trim_start_matches("0X");
+                    u8::from_str_radix(byte, 16)
+                        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid byte in capture"))
+                })
+                .collect::<io::Result<Vec<u8>>>()
+                .map(Some)
+        }
+    }
+}
+
+/// Sends MCTP messages over an SMBus adapter.
+pub struct Mctp<I> {
+    inner: I2c<I>,
+    address: u16,
+    mtu: usize,
+    seq: u8,
+}
+
+impl<I: AsRawFd> Mctp<I> {
+    /// Creates a sender using `address` as its own slave address, with the
+    /// baseline transmission unit.
+    pub fn new(i2c: I2c<I>, address: u16) -> Self {
+        Mctp {
+            inner: i2c,
+            address,
+            mtu: BASELINE_TRANSMISSION_UNIT,
+            seq: 0,
+        }
+    }
+
+    /// Sends a single packet.
+    pub fn send_packet(&mut self, packet: &Packet) -> io::Result<()> {
+        let data = packet.to_bytes();
+        self.inner.i2c_transfer(&mut [I2cMessage::Write {
+            address: packet.dest_address,
+            data: &data,
+            flags: WriteFlags::default(),
+        }])
+    }
+
+    /// Fragments and sends a message to the endpoint at `dest_address`.
+    pub fn send(&mut self, dest_address: u16, message: &Message) -> io::Result<()> {
+        let packets = fragment(message, dest_address, self.address, self.mtu, self.seq);
+        for packet in &packets {
+            self.send_packet(packet)?;
+            self.seq = (packet.header.seq + 1) & 0x03;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I> Mctp<I> {
+    /// The slave address used as the source of sent packets.
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// The transmission unit used to fragment messages.
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Sets the transmission unit used to fragment messages.
+    ///
+    /// It should not exceed the transmission unit negotiated with the
+    /// destination endpoint.
+    pub fn set_mtu(&mut self, mtu: usize) -> io::Result<()> {
+        if !(BASELINE_TRANSMISSION_UNIT..=MAX_TRANSMISSION_UNIT).contains(&mtu) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "MCTP transmission unit out of range",
+            ))
+        }
+        self.mtu = mtu;
+        Ok(())
+    }
+
+    /// Returns the underlying adapter.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying adapter.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying adapter.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,246 @@
+//! PCA954x I2C multiplexers and switches, driven from userspace.
+//!
+//! This is only useful when the kernel `i2c-mux-pca954x` driver is not bound
+//! to the device; otherwise each channel appears as its own adapter.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{mux::{Chip, Mux}, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut mux = Mux::new(I2c::from_path("/dev/i2c-1")?, Chip::Pca9548, 0x70);
+//! let temperature = mux.channel(3)?.transaction(|i2c| {
+//!     i2c.smbus_set_slave_address(0x48, false)?;
+//!     i2c.smbus_read_word_data(0x00)
+//! })?;
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::I2c,
+    std::{io, os::unix::io::AsRawFd},
+};
+
+/// The base slave address of PCA954x devices.
+pub const PCA954X_ADDRESS: u16 = 0x70;
+
+const MUX_ENABLE: u8 = 0x04;
+const DESELECTED: u8 = 0x00;
+
+/// Supported multiplexer and switch chips.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Chip {
+    /// 2 channel multiplexer.
+    Pca9540,
+    /// 2 channel multiplexer with interrupt logic.
+    Pca9542,
+    /// 2 channel switch with interrupt logic.
+    Pca9543,
+    /// 4 channel multiplexer with interrupt logic.
+    Pca9544,
+    /// 4 channel switch with interrupt logic.
+    Pca9545,
+    /// 4 channel switch.
+    Pca9546,
+    /// 8 channel switch.
+    Pca9548,
+}
+
+impl Chip {
+    /// The number of downstream channels.
+    pub fn channels(self) -> u8 {
+        match self {
+            Chip::Pca9540 | Chip::Pca9542 | Chip::Pca9543 => 2,
+            Chip::Pca9544 | Chip::Pca9545 | Chip::Pca9546 => 4,
+            Chip::Pca9548 => 8,
+        }
+    }
+
+    /// Whether the chip is a switch, able to enable several channels at
+    /// once, rather than a multiplexer.
+    pub fn is_switch(self) -> bool {
+        !matches!(self, Chip::Pca9540 | Chip::Pca9542 | Chip::Pca9544)
+    }
+
+    /// The control register value that selects only `channel`.
+    ///
+    /// ```rust
+    /// use i2c_linux::mux::Chip;
+    ///
+    /// assert_eq!(Chip::Pca9544.control(3), Some(0x07));
+    /// assert_eq!(Chip::Pca9548.control(3), Some(0x08));
+    /// assert_eq!(Chip::Pca9548.control(8), None);
+    /// ```
+    pub fn control(self, channel: u8) -> Option<u8> {
+        if channel >= self.channels() {
+            None
+        } else if self.is_switch() {
+            Some(1 << channel)
+        } else {
+            Some(MUX_ENABLE | channel)
+        }
+    }
+}
+
+/// A PCA954x multiplexer or switch.
+pub struct Mux<I> {
+    inner: I2c<I>,
+    chip: Chip,
+    address: u16,
+    control: Option<u8>,
+    deselect: bool,
+}
+
+impl<I: AsRawFd> Mux<I> {
+    /// Creates a handle for the chip at `address`.
+    ///
+    /// The current selection is unknown until the first channel is selected.
+    pub fn new(i2c: I2c<I>, chip: Chip, address: u16) -> Self {
+        Mux {
+            inner: i2c,
+            chip,
+            address,
+            control: None,
+            deselect: false,
+        }
+    }
+
+    /// Selects `channel`, unless it is already known to be selected.
+    pub fn select(&mut self, channel: u8) -> io::Result<()> {
+        match self.chip.control(channel) {
+            Some(control) => self.write_control(control),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "mux channel out of range")),
+        }
+    }
+
+    /// Disconnects all channels.
+    pub fn deselect(&mut self) -> io::Result<()> {
+        self.write_control(DESELECTED)
+    }
+
+    /// Reads the control register from the chip, refreshing the cached
+    /// selection.
+    pub fn read_control(&mut self) -> io::Result<u8> {
+        let control = self.with_address(|i2c| i2c.smbus_read_byte())?;
+        self.control = Some(control);
+        Ok(control)
+    }
+
+    /// Borrows a handle scoped to `channel`.
+    pub fn channel(&mut self, channel: u8) -> io::Result<Channel<'_, I>> {
+        if channel < self.chip.channels() {
+            Ok(Channel { mux: self, channel })
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "mux channel out of range"))
+        }
+    }
+
+    fn write_control(&mut self, control: u8) -> io::Result<()> {
+        if self.control == Some(control) {
+            return Ok(())
+        }
+
+        // Forget the selection if the write fails part way
+        self.control = None;
+        self.with_address(|i2c| i2c.smbus_write_byte(control))?;
+        self.control = Some(control);
+        Ok(())
+    }
+
+    fn with_address<R, F: FnOnce(&mut I2c<I>) -> io::Result<R>>(&mut self, f: F) -> io::Result<R> {
+        let previous = self.inner.address.map(|address| (address, self.inner.address_10bit));
+        self.inner.smbus_set_slave_address(self.address, false)?;
+        let res = f(&mut self.inner);
+        if let Some((address, tenbit)) = previous {
+            self.inner.smbus_set_slave_address(address, tenbit)?;
+        }
+        res
+    }
+}
+
+impl<I> Mux<I> {
+    /// The chip type.
+    pub fn chip(&self) -> Chip {
+        self.chip
+    }
+
+    /// The slave address of the chip.
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// The selected channel, if known.
+    pub fn selected(&self) -> Option<u8> {
+        let control = self.control?;
+        (0..self.chip.channels()).find(|&channel| self.chip.control(channel) == Some(control))
+    }
+
+    /// Whether channels are deselected after each channel transaction.
+    pub fn deselect_after(&self) -> bool {
+        self.deselect
+    }
+
+    /// Sets whether channels are deselected after each channel transaction,
+    /// which is necessary when devices on different channels share an
+    /// address with a device on the parent bus.
+    pub fn set_deselect_after(&mut self, deselect: bool) {
+        self.deselect = deselect;
+    }
+
+    /// Forgets the cached selection, such as after another process or a
+    /// reset has changed it.
+    pub fn invalidate(&mut self) {
+        self.control = None;
+    }
+
+    /// Returns the underlying adapter.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying adapter.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying adapter.
+    ///
+    /// The cached selection is assumed to remain valid.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
+
+/// A handle to a single channel of a [Mux].
+pub struct Channel<'a, I> {
+    mux: &'a mut Mux<I>,
+    channel: u8,
+}
+
+impl<'a, I: AsRawFd> Channel<'a, I> {
+    /// Selects the channel and runs `f` against the adapter.
+    ///
+    /// The channel is deselected afterwards if the mux is configured to do
+    /// so, even if `f` fails.
+    pub fn transaction<R, F: FnOnce(&mut I2c<I>) -> io::Result<R>>(&mut self, f: F) -> io::Result<R> {
+        self.mux.select(self.channel)?;
+        let res = f(&mut self.mux.inner);
+        if self.mux.deselect {
+            let deselect = self.mux.deselect();
+            if res.is_ok() {
+                deselect?;
+            }
+        }
+        res
+    }
+}
+
+impl<'a, I> Channel<'a, I> {
+    /// The channel index.
+    pub fn index(&self) -> u8 {
+        self.channel
+    }
+}
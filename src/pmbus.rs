@@ -0,0 +1,794 @@
+//! PMBus power management client.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{pmbus::Pmbus, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut pmbus = Pmbus::new(I2c::from_path("/dev/i2c-0")?, 0x40)?;
+//! pmbus.set_page(0)?;
+//! println!("{} V", pmbus.read_vout()?);
+//! println!("{:?}", pmbus.status_word()?);
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::I2c,
+    bitflags::bitflags,
+    std::{io, os::unix::io::AsRawFd},
+};
+
+/// Standard PMBus command codes.
+#[allow(missing_docs)]
+pub mod command {
+    pub const PAGE: u8 = 0x00;
+    pub const OPERATION: u8 = 0x01;
+    pub const ON_OFF_CONFIG: u8 = 0x02;
+    pub const CLEAR_FAULTS: u8 = 0x03;
+    pub const PHASE: u8 = 0x04;
+    pub const PAGE_PLUS_WRITE: u8 = 0x05;
+    pub const PAGE_PLUS_READ: u8 = 0x06;
+    pub const ZONE_CONFIG: u8 = 0x07;
+    pub const ZONE_ACTIVE: u8 = 0x08;
+    pub const WRITE_PROTECT: u8 = 0x10;
+    pub const STORE_DEFAULT_ALL: u8 = 0x11;
+    pub const RESTORE_DEFAULT_ALL: u8 = 0x12;
+    pub const STORE_DEFAULT_CODE: u8 = 0x13;
+    pub const RESTORE_DEFAULT_CODE: u8 = 0x14;
+    pub const STORE_USER_ALL: u8 = 0x15;
+    pub const RESTORE_USER_ALL: u8 = 0x16;
+    pub const STORE_USER_CODE: u8 = 0x17;
+    pub const RESTORE_USER_CODE: u8 = 0x18;
+    pub const CAPABILITY: u8 = 0x19;
+    pub const QUERY: u8 = 0x1a;
+    pub const SMBALERT_MASK: u8 = 0x1b;
+    pub const VOUT_MODE: u8 = 0x20;
+    pub const VOUT_COMMAND: u8 = 0x21;
+    pub const VOUT_TRIM: u8 = 0x22;
+    pub const VOUT_CAL_OFFSET: u8 = 0x23;
+    pub const VOUT_MAX: u8 = 0x24;
+    pub const VOUT_MARGIN_HIGH: u8 = 0x25;
+    pub const VOUT_MARGIN_LOW: u8 = 0x26;
+    pub const VOUT_TRANSITION_RATE: u8 = 0x27;
+    pub const VOUT_DROOP: u8 = 0x28;
+    pub const VOUT_SCALE_LOOP: u8 = 0x29;
+    pub const VOUT_SCALE_MONITOR: u8 = 0x2a;
+    pub const VOUT_MIN: u8 = 0x2b;
+    pub const COEFFICIENTS: u8 = 0x30;
+    pub const POUT_MAX: u8 = 0x31;
+    pub const MAX_DUTY: u8 = 0x32;
+    pub const FREQUENCY_SWITCH: u8 = 0x33;
+    pub const POWER_MODE: u8 = 0x34;
+    pub const VIN_ON: u8 = 0x35;
+    pub const VIN_OFF: u8 = 0x36;
+    pub const INTERLEAVE: u8 = 0x37;
+    pub const IOUT_CAL_GAIN: u8 = 0x38;
+    pub const IOUT_CAL_OFFSET: u8 = 0x39;
+    pub const FAN_CONFIG_1_2: u8 = 0x3a;
+    pub const FAN_COMMAND_1: u8 = 0x3b;
+    pub const FAN_COMMAND_2: u8 = 0x3c;
+    pub const FAN_CONFIG_3_4: u8 = 0x3d;
+    pub const FAN_COMMAND_3: u8 = 0x3e;
+    pub const FAN_COMMAND_4: u8 = 0x3f;
+    pub const VOUT_OV_FAULT_LIMIT: u8 = 0x40;
+    pub const VOUT_OV_FAULT_RESPONSE: u8 = 0x41;
+    pub const VOUT_OV_WARN_LIMIT: u8 = 0x42;
+    pub const VOUT_UV_WARN_LIMIT: u8 = 0x43;
+    pub const VOUT_UV_FAULT_LIMIT: u8 = 0x44;
+    pub const VOUT_UV_FAULT_RESPONSE: u8 = 0x45;
+    pub const IOUT_OC_FAULT_LIMIT: u8 = 0x46;
+    pub const IOUT_OC_FAULT_RESPONSE: u8 = 0x47;
+    pub const IOUT_OC_LV_FAULT_LIMIT: u8 = 0x48;
+    pub const IOUT_OC_LV_FAULT_RESPONSE: u8 = 0x49;
+    pub const IOUT_OC_WARN_LIMIT: u8 = 0x4a;
+    pub const IOUT_UC_FAULT_LIMIT: u8 = 0x4b;
+    pub const IOUT_UC_FAULT_RESPONSE: u8 = 0x4c;
+    pub const OT_FAULT_LIMIT: u8 = 0x4f;
+    pub const OT_FAULT_RESPONSE: u8 = 0x50;
+    pub const OT_WARN_LIMIT: u8 = 0x51;
+    pub const UT_WARN_LIMIT: u8 = 0x52;
+    pub const UT_FAULT_LIMIT: u8 = 0x53;
+    pub const UT_FAULT_RESPONSE: u8 = 0x54;
+    pub const VIN_OV_FAULT_LIMIT: u8 = 0x55;
+    pub const VIN_OV_FAULT_RESPONSE: u8 = 0x56;
+    pub const VIN_OV_WARN_LIMIT: u8 = 0x57;
+    pub const VIN_UV_WARN_LIMIT: u8 = 0x58;
+    pub const VIN_UV_FAULT_LIMIT: u8 = 0x59;
+    pub const VIN_UV_FAULT_RESPONSE: u8 = 0x5a;
+    pub const IIN_OC_FAULT_LIMIT: u8 = 0x5b;
+    pub const IIN_OC_FAULT_RESPONSE: u8 = 0x5c;
+    pub const IIN_OC_WARN_LIMIT: u8 = 0x5d;
+    pub const POWER_GOOD_ON: u8 = 0x5e;
+    pub const POWER_GOOD_OFF: u8 = 0x5f;
+    pub const TON_DELAY: u8 = 0x60;
+    pub const TON_RISE: u8 = 0x61;
+    pub const TON_MAX_FAULT_LIMIT: u8 = 0x62;
+    pub const TON_MAX_FAULT_RESPONSE: u8 = 0x63;
+    pub const TOFF_DELAY: u8 = 0x64;
+    pub const TOFF_FALL: u8 = 0x65;
+    pub const TOFF_MAX_WARN_LIMIT: u8 = 0x66;
+    pub const POUT_OP_FAULT_LIMIT: u8 = 0x68;
+    pub const POUT_OP_FAULT_RESPONSE: u8 = 0x69;
+    pub const POUT_OP_WARN_LIMIT: u8 = 0x6a;
+    pub const PIN_OP_WARN_LIMIT: u8 = 0x6b;
+    pub const STATUS_BYTE: u8 = 0x78;
+    pub const STATUS_WORD: u8 = 0x79;
+    pub const STATUS_VOUT: u8 = 0x7a;
+    pub const STATUS_IOUT: u8 = 0x7b;
+    pub const STATUS_INPUT: u8 = 0x7c;
+    pub const STATUS_TEMPERATURE: u8 = 0x7d;
+    pub const STATUS_CML: u8 = 0x7e;
+    pub const STATUS_OTHER: u8 = 0x7f;
+    pub const STATUS_MFR_SPECIFIC: u8 = 0x80;
+    pub const STATUS_FANS_1_2: u8 = 0x81;
+    pub const STATUS_FANS_3_4: u8 = 0x82;
+    pub const READ_KWH_IN: u8 = 0x83;
+    pub const READ_KWH_OUT: u8 = 0x84;
+    pub const READ_KWH_CONFIG: u8 = 0x85;
+    pub const READ_EIN: u8 = 0x86;
+    pub const READ_EOUT: u8 = 0x87;
+    pub const READ_VIN: u8 = 0x88;
+    pub const READ_IIN: u8 = 0x89;
+    pub const READ_VCAP: u8 = 0x8a;
+    pub const READ_VOUT: u8 = 0x8b;
+    pub const READ_IOUT: u8 = 0x8c;
+    pub const READ_TEMPERATURE_1: u8 = 0x8d;
+    pub const READ_TEMPERATURE_2: u8 = 0x8e;
+    pub const READ_TEMPERATURE_3: u8 = 0x8f;
+    pub const READ_FAN_SPEED_1: u8 = 0x90;
+    pub const READ_FAN_SPEED_2: u8 = 0x91;
+    pub const READ_FAN_SPEED_3: u8 = 0x92;
+    pub const READ_FAN_SPEED_4: u8 = 0x93;
+    pub const READ_DUTY_CYCLE: u8 = 0x94;
+    pub const READ_FREQUENCY: u8 = 0x95;
+    pub const READ_POUT: u8 = 0x96;
+    pub const READ_PIN: u8 = 0x97;
+    pub const PMBUS_REVISION: u8 = 0x98;
+    pub const MFR_ID: u8 = 0x99;
+    pub const MFR_MODEL: u8 = 0x9a;
+    pub const MFR_REVISION: u8 = 0x9b;
+    pub const MFR_LOCATION: u8 = 0x9c;
+    pub const MFR_DATE: u8 = 0x9d;
+    pub const MFR_SERIAL: u8 = 0x9e;
+    pub const APP_PROFILE_SUPPORT: u8 = 0x9f;
+    pub const MFR_VIN_MIN: u8 = 0xa0;
+    pub const MFR_VIN_MAX: u8 = 0xa1;
+    pub const MFR_IIN_MAX: u8 = 0xa2;
+    pub const MFR_PIN_MAX: u8 = 0xa3;
+    pub const MFR_VOUT_MIN: u8 = 0xa4;
+    pub const MFR_VOUT_MAX: u8 = 0xa5;
+    pub const MFR_IOUT_MAX: u8 = 0xa6;
+    pub const MFR_POUT_MAX: u8 = 0xa7;
+    pub const MFR_TAMBIENT_MAX: u8 = 0xa8;
+    pub const MFR_TAMBIENT_MIN: u8 = 0xa9;
+    pub const MFR_EFFICIENCY_LL: u8 = 0xaa;
+    pub const MFR_EFFICIENCY_HL: u8 = 0xab;
+    pub const MFR_PIN_ACCURACY: u8 = 0xac;
+    pub const IC_DEVICE_ID: u8 = 0xad;
+    pub const IC_DEVICE_REV: u8 = 0xae;
+    pub const USER_DATA_00: u8 = 0xb0;
+    pub const MFR_MAX_TEMP_1: u8 = 0xc0;
+    pub const MFR_MAX_TEMP_2: u8 = 0xc1;
+    pub const MFR_MAX_TEMP_3: u8 = 0xc2;
+}
+
+/// The `PAGE` or `PHASE` value addressing all pages or phases at once.
+pub const ALL: u8 = 0xff;
+
+bitflags! {
+    /// `STATUS_WORD` summary flags. The low byte is `STATUS_BYTE`.
+    pub struct StatusWord: u16 {
+        /// An output voltage fault or warning has occurred.
+        const VOUT = 1 << 15;
+        /// An output current or power fault or warning has occurred.
+        const IOUT_POUT = 1 << 14;
+        /// An input voltage, current or power fault or warning has occurred.
+        const INPUT = 1 << 13;
+        /// A manufacturer specific fault or warning has occurred.
+        const MFR_SPECIFIC = 1 << 12;
+        /// The POWER_GOOD signal is negated.
+        const POWER_GOOD_N = 1 << 11;
+        /// A fan or airflow fault or warning has occurred.
+        const FANS = 1 << 10;
+        /// A bit in `STATUS_OTHER` is set.
+        const OTHER = 1 << 9;
+        /// A fault type not given in bits 15:1 of `STATUS_WORD` has occurred.
+        const UNKNOWN = 1 << 8;
+        /// The device was busy and unable to respond.
+        const BUSY = 1 << 7;
+        /// The unit is not providing power to the output.
+        const OFF = 1 << 6;
+        /// An output overvoltage fault has occurred.
+        const VOUT_OV_FAULT = 1 << 5;
+        /// An output overcurrent fault has occurred.
+        const IOUT_OC_FAULT = 1 << 4;
+        /// An input undervoltage fault has occurred.
+        const VIN_UV_FAULT = 1 << 3;
+        /// A temperature fault or warning has occurred.
+        const TEMPERATURE = 1 << 2;
+        /// A communications, memory or logic fault has occurred.
+        const CML = 1 << 1;
+        /// A fault or warning not listed in bits 7:1 has occurred.
+        const NONE_OF_THE_ABOVE = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// `STATUS_VOUT` flags.
+    pub struct StatusVout: u8 {
+        /// Overvoltage fault.
+        const OV_FAULT = 1 << 7;
+        /// Overvoltage warning.
+        const OV_WARNING = 1 << 6;
+        /// Undervoltage warning.
+        const UV_WARNING = 1 << 5;
+        /// Undervoltage fault.
+        const UV_FAULT = 1 << 4;
+        /// `VOUT_MAX` or `VOUT_MIN` warning.
+        const MAX_MIN_WARNING = 1 << 3;
+        /// `TON_MAX` fault.
+        const TON_MAX_FAULT = 1 << 2;
+        /// `TOFF_MAX` warning.
+        const TOFF_MAX_WARNING = 1 << 1;
+        /// Power on tracking error.
+        const TRACKING_ERROR = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// `STATUS_IOUT` flags.
+    pub struct StatusIout: u8 {
+        /// Overcurrent fault.
+        const OC_FAULT = 1 << 7;
+        /// Overcurrent and low voltage fault.
+        const OC_LV_FAULT = 1 << 6;
+        /// Overcurrent warning.
+        const OC_WARNING = 1 << 5;
+        /// Undercurrent fault.
+        const UC_FAULT = 1 << 4;
+        /// Current share fault.
+        const CURRENT_SHARE_FAULT = 1 << 3;
+        /// The unit is in power limiting mode.
+        const POWER_LIMITING = 1 << 2;
+        /// Output overpower fault.
+        const POUT_OP_FAULT = 1 << 1;
+        /// Output overpower warning.
+        const POUT_OP_WARNING = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// `STATUS_INPUT` flags.
+    pub struct StatusInput: u8 {
+        /// Input overvoltage fault.
+        const VIN_OV_FAULT = 1 << 7;
+        /// Input overvoltage warning.
+        const VIN_OV_WARNING = 1 << 6;
+        /// Input undervoltage warning.
+        const VIN_UV_WARNING = 1 << 5;
+        /// Input undervoltage fault.
+        const VIN_UV_FAULT = 1 << 4;
+        /// The unit is off due to insufficient input voltage.
+        const UNIT_OFF_LOW_VIN = 1 << 3;
+        /// Input overcurrent fault.
+        const IIN_OC_FAULT = 1 << 2;
+        /// Input overcurrent warning.
+        const IIN_OC_WARNING = 1 << 1;
+        /// Input overpower warning.
+        const PIN_OP_WARNING = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// `STATUS_TEMPERATURE` flags.
+    pub struct StatusTemperature: u8 {
+        /// Overtemperature fault.
+        const OT_FAULT = 1 << 7;
+        /// Overtemperature warning.
+        const OT_WARNING = 1 << 6;
+        /// Undertemperature warning.
+        const UT_WARNING = 1 << 5;
+        /// Undertemperature fault.
+        const UT_FAULT = 1 << 4;
+    }
+}
+
+bitflags! {
+    /// `STATUS_CML` communication, logic and memory flags.
+    pub struct StatusCml: u8 {
+        /// An invalid or unsupported command was received.
+        const INVALID_COMMAND = 1 << 7;
+        /// Invalid or unsupported data was received.
+        const INVALID_DATA = 1 << 6;
+        /// Packet error check failed.
+        const PEC_FAILED = 1 << 5;
+        /// Memory fault detected.
+        const MEMORY_FAULT = 1 << 4;
+        /// Processor fault detected.
+        const PROCESSOR_FAULT = 1 << 3;
+        /// A communication fault other than those listed has occurred.
+        const OTHER_COMMUNICATION_FAULT = 1 << 1;
+        /// Another memory or logic fault has occurred.
+        const OTHER_MEMORY_LOGIC_FAULT = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// `STATUS_OTHER` flags.
+    pub struct StatusOther: u8 {
+        /// Input A fuse or circuit breaker fault.
+        const INPUT_A_FUSE = 1 << 5;
+        /// Input B fuse or circuit breaker fault.
+        const INPUT_B_FUSE = 1 << 4;
+        /// Input A OR-ing device fault.
+        const INPUT_A_ORING = 1 << 3;
+        /// Input B OR-ing device fault.
+        const INPUT_B_ORING = 1 << 2;
+        /// Output OR-ing device fault.
+        const OUTPUT_ORING = 1 << 1;
+        /// This device was the first to assert SMBALERT#.
+        const FIRST_TO_ASSERT_SMBALERT = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// `STATUS_FANS_1_2` and `STATUS_FANS_3_4` flags.
+    ///
+    /// For `STATUS_FANS_3_4`, fans 1 and 2 refer to fans 3 and 4.
+    pub struct StatusFans: u8 {
+        /// Fan 1 fault.
+        const FAN1_FAULT = 1 << 7;
+        /// Fan 2 fault.
+        const FAN2_FAULT = 1 << 6;
+        /// Fan 1 warning.
+        const FAN1_WARNING = 1 << 5;
+        /// Fan 2 warning.
+        const FAN2_WARNING = 1 << 4;
+        /// Fan 1 speed is overridden.
+        const FAN1_SPEED_OVERRIDDEN = 1 << 3;
+        /// Fan 2 speed is overridden.
+        const FAN2_SPEED_OVERRIDDEN = 1 << 2;
+        /// Airflow fault.
+        const AIRFLOW_FAULT = 1 << 1;
+        /// Airflow warning.
+        const AIRFLOW_WARNING = 1 << 0;
+    }
+}
+
+/// The output voltage data format, as reported by `VOUT_MODE`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum VoutMode {
+    /// LINEAR16 format with the given exponent.
+    Linear(i8),
+    /// VID format with the given manufacturer specific VID code type.
+    Vid(u8),
+    /// DIRECT format, using coefficients from the datasheet or `COEFFICIENTS`.
+    Direct,
+    /// IEEE 754 half precision format.
+    Ieee754Half,
+}
+
+impl VoutMode {
+    /// Decodes a `VOUT_MODE` byte.
+    pub fn from_byte(value: u8) -> Option<Self> {
+        let parameter = value & 0x1f;
+        match value >> 5 {
+            0b000 => Some(VoutMode::Linear(sign_extend(parameter as u16, 5) as i8)),
+            0b001 => Some(VoutMode::Vid(parameter)),
+            0b010 => Some(VoutMode::Direct),
+            0b011 => Some(VoutMode::Ieee754Half),
+            _ => None,
+        }
+    }
+}
+
+/// Voltage regulator VID code tables.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum VidFormat {
+    /// Intel VR11, 6.25mV steps from 1.6V downward.
+    Vr11,
+    /// Intel VR12, 5mV steps from 0.25V.
+    Vr12,
+    /// Intel VR13, 10mV steps from 0.5V.
+    Vr13,
+    /// Intel IMVP9, 10mV steps from 0.2V.
+    Imvp9,
+    /// AMD 6.25mV steps from 1.55V downward.
+    Amd625mv,
+}
+
+impl VidFormat {
+    /// Converts a VID code to volts.
+    ///
+    /// ```rust
+    /// use i2c_linux::pmbus::VidFormat;
+    ///
+    /// assert!((VidFormat::Vr12.decode(0x65) - 0.75).abs() < 1e-9);
+    /// assert_eq!(VidFormat::Vr11.decode(0x02), 1.6);
+    /// assert_eq!(VidFormat::Vr12.decode(0x00), 0.0);
+    /// ```
+    pub fn decode(&self, vid: u16) -> f64 {
+        let vid = vid as f64;
+        match *self {
+            VidFormat::Vr11 if (2.0..=178.0).contains(&vid) => 1.6 - (vid - 2.0) * 0.00625,
+            VidFormat::Vr12 if vid >= 1.0 => 0.25 + (vid - 1.0) * 0.005,
+            VidFormat::Vr13 if vid >= 1.0 => 0.5 + (vid - 1.0) * 0.01,
+            VidFormat::Imvp9 if vid >= 1.0 => 0.2 + (vid - 1.0) * 0.01,
+            VidFormat::Amd625mv if vid <= 216.0 => 1.55 - vid * 0.00625,
+            _ => 0.0,
+        }
+    }
+}
+
+/// DIRECT format coefficients.
+///
+/// Values are converted as `X = (Y * 10^-R - b) / m`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Coefficients {
+    /// Slope coefficient.
+    pub m: i16,
+    /// Offset.
+    pub b: i16,
+    /// Exponent.
+    pub r: i8,
+}
+
+impl Coefficients {
+    /// Converts a DIRECT format value to real world units.
+    ///
+    /// ```rust
+    /// use i2c_linux::pmbus::Coefficients;
+    ///
+    /// let coefficients = Coefficients { m: 5, b: 0, r: -1 };
+    /// assert_eq!(coefficients.decode(150), 300.0);
+    /// assert_eq!(coefficients.encode(300.0), 150);
+    /// ```
+    pub fn decode(&self, value: i16) -> f64 {
+        (value as f64 * 10f64.powi(-(self.r as i32)) - self.b as f64) / self.m as f64
+    }
+
+    /// Converts a real world value to DIRECT format.
+    pub fn encode(&self, value: f64) -> i16 {
+        ((value * self.m as f64 + self.b as f64) * 10f64.powi(self.r as i32)).round() as i16
+    }
+}
+
+/// Converts a LINEAR11 value to real world units.
+///
+/// ```rust
+/// use i2c_linux::pmbus;
+///
+/// assert_eq!(pmbus::linear11_decode(0xf190), 100.0);
+/// assert_eq!(pmbus::linear11_decode(0x03ff), 1023.0);
+/// assert_eq!(pmbus::linear11_decode(0xfff7), -4.5);
+/// assert_eq!(pmbus::linear11_encode(100.0), 0xeb20);
+/// assert_eq!(pmbus::linear11_decode(pmbus::linear11_encode(-4.5)), -4.5);
+/// ```
+pub fn linear11_decode(value: u16) -> f64 {
+    let exponent = sign_extend(value >> 11, 5);
+    let mantissa = sign_extend(value & 0x07ff, 11);
+    mantissa as f64 * 2f64.powi(exponent as i32)
+}
+
+/// Converts a real world value to LINEAR11, choosing the exponent that
+/// preserves the most precision.
+pub fn linear11_encode(value: f64) -> u16 {
+    for exponent in -16i32..=15 {
+        let mantissa = (value / 2f64.powi(exponent)).round();
+        if (-1024.0..=1023.0).contains(&mantissa) {
+            return ((exponent as u16 & 0x1f) << 11) | (mantissa as i16 as u16 & 0x07ff)
+        }
+    }
+
+    if value < 0.0 {
+        0x7c00
+    } else {
+        0x7bff
+    }
+}
+
+/// Converts a LINEAR16 value to real world units, using the exponent from
+/// `VOUT_MODE`.
+///
+/// ```rust
+/// use i2c_linux::pmbus;
+///
+/// assert_eq!(pmbus::linear16_decode(0x0d80, -12), 0.84375);
+/// ```
+pub fn linear16_decode(value: u16, exponent: i8) -> f64 {
+    value as f64 * 2f64.powi(exponent as i32)
+}
+
+/// Converts a real world value to LINEAR16, using the exponent from
+/// `VOUT_MODE`.
+pub fn linear16_encode(value: f64, exponent: i8) -> u16 {
+    // float to int casts saturate
+    (value / 2f64.powi(exponent as i32)).round() as u16
+}
+
+/// Converts an IEEE 754 half precision value.
+pub fn ieee754_half_decode(value: u16) -> f64 {
+    let sign = if value & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((value >> 10) & 0x1f) as i32;
+    let fraction = (value & 0x03ff) as f64;
+    sign * match exponent {
+        0 => fraction * 2f64.powi(-24),
+        0x1f if fraction == 0.0 => f64::INFINITY,
+        0x1f => f64::NAN,
+        exponent => (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+fn sign_extend(value: u16, bits: u32) -> i16 {
+    let shift = 16 - bits;
+    ((value << shift) as i16) >> shift
+}
+
+/// A PMBus device.
+///
+/// The currently selected `PAGE` and `PHASE` are cached to avoid redundant
+/// writes.
+pub struct Pmbus<I> {
+    inner: I2c<I>,
+    page: Option<u8>,
+    phase: Option<u8>,
+    vid_format: VidFormat,
+    vout_coefficients: Option<Coefficients>,
+}
+
+impl<I> Pmbus<I> {
+    /// Sets the VID code table used to convert VID formatted output voltages.
+    ///
+    /// Defaults to `VidFormat::Vr12`.
+    pub fn set_vid_format(&mut self, format: VidFormat) {
+        self.vid_format = format;
+    }
+
+    /// Sets the coefficients used to convert DIRECT formatted output
+    /// voltages.
+    pub fn set_vout_coefficients(&mut self, coefficients: Option<Coefficients>) {
+        self.vout_coefficients = coefficients;
+    }
+
+    /// The currently selected page, if known.
+    pub fn page(&self) -> Option<u8> {
+        self.page
+    }
+
+    /// The currently selected phase, if known.
+    pub fn phase(&self) -> Option<u8> {
+        self.phase
+    }
+
+    /// Forgets the cached page and phase, in case another bus user may have
+    /// changed them.
+    pub fn invalidate(&mut self) {
+        self.page = None;
+        self.phase = None;
+    }
+
+    /// Consumes the handle to return the underlying I2C bus.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying I2C bus.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying I2C bus.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
+
+impl<I: AsRawFd> Pmbus<I> {
+    /// Creates a new PMBus client for the device at `address`.
+    pub fn new(mut i2c: I2c<I>, address: u16) -> io::Result<Self> {
+        i2c.smbus_set_slave_address(address, false)?;
+
+        Ok(Pmbus {
+            inner: i2c,
+            page: None,
+            phase: None,
+            vid_format: VidFormat::Vr12,
+            vout_coefficients: None,
+        })
+    }
+
+    /// Enable or disable SMBus Packet Error Checking.
+    pub fn set_pec(&mut self, pec: bool) -> io::Result<()> {
+        self.inner.smbus_set_pec(pec)
+    }
+
+    /// Selects the page that subsequent paged commands apply to.
+    ///
+    /// Changing the page forgets the cached phase, as each page has its own.
+    pub fn set_page(&mut self, page: u8) -> io::Result<()> {
+        if self.page != Some(page) {
+            self.page = None;
+            self.phase = None;
+            self.inner.smbus_write_byte_data(command::PAGE, page)?;
+            self.page = Some(page);
+        }
+
+        Ok(())
+    }
+
+    /// Selects the phase of the current page that subsequent commands apply
+    /// to.
+    pub fn set_phase(&mut self, phase: u8) -> io::Result<()> {
+        if self.phase != Some(phase) {
+            self.phase = None;
+            self.inner.smbus_write_byte_data(command::PHASE, phase)?;
+            self.phase = Some(phase);
+        }
+
+        Ok(())
+    }
+
+    /// Sends a command with no data, such as `CLEAR_FAULTS`.
+    pub fn send_byte(&mut self, command: u8) -> io::Result<()> {
+        self.inner.smbus_write_byte(command)
+    }
+
+    /// Reads a byte sized command.
+    pub fn read_byte(&mut self, command: u8) -> io::Result<u8> {
+        self.inner.smbus_read_byte_data(command)
+    }
+
+    /// Writes a byte sized command.
+    pub fn write_byte(&mut self, command: u8, value: u8) -> io::Result<()> {
+        self.inner.smbus_write_byte_data(command, value)
+    }
+
+    /// Reads a word sized command.
+    pub fn read_word(&mut self, command: u8) -> io::Result<u16> {
+        self.inner.smbus_read_word_data(command)
+    }
+
+    /// Writes a word sized command.
+    pub fn write_word(&mut self, command: u8, value: u16) -> io::Result<()> {
+        self.inner.smbus_write_word_data(command, value)
+    }
+
+    /// Reads a block command such as `MFR_ID`.
+    ///
+    /// Returns the amount of data read.
+    pub fn read_block(&mut self, command: u8, value: &mut [u8]) -> io::Result<usize> {
+        self.inner.smbus_read_block_data(command, value)
+    }
+
+    /// Reads a block command as a string, such as `MFR_MODEL` or
+    /// `MFR_SERIAL`.
+    pub fn read_string(&mut self, command: u8) -> io::Result<String> {
+        let mut data = [0u8; 32];
+        let len = self.read_block(command, &mut data)?;
+        Ok(String::from_utf8_lossy(&data[..len])
+            .trim_end_matches(&[' ', '\0'][..])
+            .into())
+    }
+
+    /// Clears all latched status bits.
+    pub fn clear_faults(&mut self) -> io::Result<()> {
+        self.send_byte(command::CLEAR_FAULTS)
+    }
+
+    /// Reads `STATUS_WORD`.
+    pub fn status_word(&mut self) -> io::Result<StatusWord> {
+        self.read_word(command::STATUS_WORD).map(StatusWord::from_bits_truncate)
+    }
+
+    /// Reads `STATUS_VOUT`.
+    pub fn status_vout(&mut self) -> io::Result<StatusVout> {
+        self.read_byte(command::STATUS_VOUT).map(StatusVout::from_bits_truncate)
+    }
+
+    /// Reads `STATUS_IOUT`.
+    pub fn status_iout(&mut self) -> io::Result<StatusIout> {
+        self.read_byte(command::STATUS_IOUT).map(StatusIout::from_bits_truncate)
+    }
+
+    /// Reads `STATUS_INPUT`.
+    pub fn status_input(&mut self) -> io::Result<StatusInput> {
+        self.read_byte(command::STATUS_INPUT)
+            .map(StatusInput::from_bits_truncate)
+    }
+
+    /// Reads `STATUS_TEMPERATURE`.
+    pub fn status_temperature(&mut self) -> io::Result<StatusTemperature> {
+        self.read_byte(command::STATUS_TEMPERATURE)
+            .map(StatusTemperature::from_bits_truncate)
+    }
+
+    /// Reads `STATUS_CML`.
+    pub fn status_cml(&mut self) -> io::Result<StatusCml> {
+        self.read_byte(command::STATUS_CML).map(StatusCml::from_bits_truncate)
+    }
+
+    /// Reads `STATUS_OTHER`.
+    pub fn status_other(&mut self) -> io::Result<StatusOther> {
+        self.read_byte(command::STATUS_OTHER)
+            .map(StatusOther::from_bits_truncate)
+    }
+
+    /// Reads `STATUS_MFR_SPECIFIC`, whose meaning is defined by the
+    /// manufacturer.
+    pub fn status_mfr_specific(&mut self) -> io::Result<u8> {
+        self.read_byte(command::STATUS_MFR_SPECIFIC)
+    }
+
+    /// Reads `STATUS_FANS_1_2`.
+    pub fn status_fans_1_2(&mut self) -> io::Result<StatusFans> {
+        self.read_byte(command::STATUS_FANS_1_2)
+            .map(StatusFans::from_bits_truncate)
+    }
+
+    /// Reads `STATUS_FANS_3_4`.
+    pub fn status_fans_3_4(&mut self) -> io::Result<StatusFans> {
+        self.read_byte(command::STATUS_FANS_3_4)
+            .map(StatusFans::from_bits_truncate)
+    }
+
+    /// Reads and decodes `VOUT_MODE`.
+    pub fn vout_mode(&mut self) -> io::Result<VoutMode> {
+        let mode = self.read_byte(command::VOUT_MODE)?;
+        VoutMode::from_byte(mode).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown PMBus VOUT_MODE"))
+    }
+
+    /// Reads the DIRECT format coefficients the device uses for `command`.
+    pub fn read_coefficients(&mut self, command: u8, read: bool) -> io::Result<Coefficients> {
+        let mut data = [0u8; 5];
+        let len = self
+            .inner
+            .smbus_block_process_call(command::COEFFICIENTS, &[command, read as u8], &mut data)?;
+        if len < data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "short PMBus COEFFICIENTS reply",
+            ))
+        }
+
+        Ok(Coefficients {
+            m: i16::from_le_bytes([data[0], data[1]]),
+            b: i16::from_le_bytes([data[2], data[3]]),
+            r: data[4] as i8,
+        })
+    }
+
+    /// Reads a LINEAR11 formatted command such as `READ_IOUT` or
+    /// `READ_TEMPERATURE_1`.
+    pub fn read_linear11(&mut self, command: u8) -> io::Result<f64> {
+        self.read_word(command).map(linear11_decode)
+    }
+
+    /// Reads a DIRECT formatted command.
+    pub fn read_direct(&mut self, command: u8, coefficients: &Coefficients) -> io::Result<f64> {
+        self.read_word(command).map(|v| coefficients.decode(v as i16))
+    }
+
+    /// Reads an output voltage command such as `READ_VOUT` or `VOUT_COMMAND`,
+    /// converting it according to the format given by `VOUT_MODE`.
+    pub fn read_vout_command(&mut self, command: u8) -> io::Result<f64> {
+        let mode = self.vout_mode()?;
+        let value = self.read_word(command)?;
+        match mode {
+            VoutMode::Linear(exponent) => Ok(linear16_decode(value, exponent)),
+            VoutMode::Vid(_) => Ok(self.vid_format.decode(value)),
+            VoutMode::Direct => self
+                .vout_coefficients
+                .map(|c| c.decode(value as i16))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "PMBus VOUT coefficients unknown")),
+            VoutMode::Ieee754Half => Ok(ieee754_half_decode(value)),
+        }
+    }
+
+    /// Reads `READ_VOUT` in volts.
+    pub fn read_vout(&mut self) -> io::Result<f64> {
+        self.read_vout_command(command::READ_VOUT)
+    }
+}
@@ -0,0 +1,217 @@
+//! Userspace retries of transient bus errors.
+//!
+//! `I2c::i2c_set_retries` only configures the adapter's own retry count,
+//! which many bus drivers ignore. A [RetryPolicy] set on an `I2c` handle
+//! instead retries every `smbus_*` and `i2c_transfer` call that fails with a
+//! retriable error.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use {
+//!     i2c_linux::{retry::{Backoff, RetryPolicy}, I2c},
+//!     std::time::Duration,
+//! };
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut i2c = I2c::from_path("/dev/i2c-1")?;
+//! i2c.set_retry_policy(Some(RetryPolicy::new(5).backoff(Backoff::Fixed(Duration::from_millis(2)))));
+//! i2c.smbus_set_slave_address(0x50, false)?;
+//! let data = i2c.smbus_read_byte_data(0x00)?;
+//! // A stricter policy for a single sequence
+//! let word = i2c.with_retry_policy(RetryPolicy::new(1), |i2c| i2c.smbus_read_word_data(0x02))?;
+//! println!("{} retries", i2c.retry_stats().retries);
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::I2c,
+    std::{
+        cmp, io,
+        os::unix::io::{AsRawFd, RawFd},
+        thread::sleep,
+        time::Duration,
+    },
+};
+
+/// The errors retried by default: `EAGAIN` (arbitration lost), `EREMOTEIO`
+/// and `ETIMEDOUT`.
+pub const DEFAULT_RETRIABLE: [i32; 3] = [libc::EAGAIN, libc::EREMOTEIO, libc::ETIMEDOUT];
+
+/// The delay between attempts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Backoff {
+    /// Retry immediately.
+    None,
+    /// Wait the same time before every retry.
+    Fixed(Duration),
+    /// Double the wait before each retry, up to a maximum.
+    Exponential {
+        /// The wait before the first retry.
+        initial: Duration,
+        /// The longest wait.
+        max: Duration,
+    },
+}
+
+/// How failed operations are retried.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    attempts: u32,
+    backoff: Backoff,
+    retriable: Vec<i32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(3).backoff(Backoff::Exponential {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(64),
+        })
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a policy making at most `attempts` attempts in total, without
+    /// any backoff, that retries the [DEFAULT_RETRIABLE] errors.
+    pub fn new(attempts: u32) -> Self {
+        RetryPolicy {
+            attempts: cmp::max(attempts, 1),
+            backoff: Backoff::None,
+            retriable: DEFAULT_RETRIABLE.to_vec(),
+        }
+    }
+
+    /// Sets the delay between attempts.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the `errno` values that are retried.
+    pub fn retriable(mut self, errnos: &[i32]) -> Self {
+        self.retriable = errnos.to_vec();
+        self
+    }
+
+    /// The maximum number of attempts.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Whether an error should be retried.
+    ///
+    /// ```rust
+    /// use {i2c_linux::retry::RetryPolicy, std::io};
+    ///
+    /// let policy = RetryPolicy::new(3);
+    /// assert!(policy.is_retriable(&io::Error::from_raw_os_error(libc::EAGAIN)));
+    /// assert!(!policy.is_retriable(&io::Error::from_raw_os_error(libc::ENXIO)));
+    /// ```
+    pub fn is_retriable(&self, e: &io::Error) -> bool {
+        e.raw_os_error()
+            .map(|errno| self.retriable.contains(&errno))
+            .unwrap_or(false)
+    }
+
+    /// The delay before retry number `retry`, counting from `0`.
+    ///
+    /// ```rust
+    /// use {
+    ///     i2c_linux::retry::{Backoff, RetryPolicy},
+    ///     std::time::Duration,
+    /// };
+    ///
+    /// let policy = RetryPolicy::new(8).backoff(Backoff::Exponential {
+    ///     initial: Duration::from_millis(1),
+    ///     max: Duration::from_millis(5),
+    /// });
+    /// assert_eq!(policy.delay(0), Duration::from_millis(1));
+    /// assert_eq!(policy.delay(2), Duration::from_millis(4));
+    /// assert_eq!(policy.delay(3), Duration::from_millis(5));
+    /// ```
+    pub fn delay(&self, retry: u32) -> Duration {
+        match self.backoff {
+            Backoff::None => Duration::from_secs(0),
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => initial
+                .checked_mul(1u32.checked_shl(retry).unwrap_or(u32::MAX))
+                .map(|delay| cmp::min(delay, max))
+                .unwrap_or(max),
+        }
+    }
+}
+
+/// Retry counters of an `I2c` handle.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RetryStats {
+    /// The number of retries made.
+    pub retries: u64,
+    /// The number of operations that still failed with a retriable error
+    /// after exhausting their attempts.
+    pub exhausted: u64,
+}
+
+impl<I: AsRawFd> I2c<I> {
+    /// Sets the policy used to retry operations on this handle, or `None` to
+    /// disable retries.
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry_policy = policy;
+    }
+
+    /// The policy used to retry operations on this handle.
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    /// Runs `f` with `policy` in place of the handle's own retry policy.
+    pub fn with_retry_policy<R, F: FnOnce(&mut Self) -> io::Result<R>>(
+        &mut self,
+        policy: RetryPolicy,
+        f: F,
+    ) -> io::Result<R> {
+        let previous = self.retry_policy.replace(policy);
+        let res = f(self);
+        self.retry_policy = previous;
+        res
+    }
+
+    /// The retries made by this handle so far.
+    pub fn retry_stats(&self) -> RetryStats {
+        self.retry_stats
+    }
+
+    /// Clears the retry counters.
+    pub fn reset_retry_stats(&mut self) {
+        self.retry_stats = RetryStats::default();
+    }
+
+    /// Runs a single bus operation according to the retry policy.
+    pub(crate) fn retry<R, F: FnMut(RawFd) -> io::Result<R>>(&mut self, mut f: F) -> io::Result<R> {
+        let fd = self.as_raw_fd();
+        let policy = match self.retry_policy {
+            Some(ref policy) => policy,
+            None => return f(fd),
+        };
+
+        let mut attempt = 1;
+        loop {
+            match f(fd) {
+                Err(ref e) if policy.is_retriable(e) && attempt < policy.attempts => {
+                    sleep(policy.delay(attempt - 1));
+                    self.retry_stats.retries += 1;
+                    attempt += 1;
+                },
+                Err(e) => {
+                    if policy.is_retriable(&e) {
+                        self.retry_stats.exhausted += 1;
+                    }
+                    return Err(e)
+                },
+                res => return res,
+            }
+        }
+    }
+}
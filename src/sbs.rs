@@ -0,0 +1,325 @@
+//! Smart Battery Data Specification driver.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{sbs::Sbs, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut battery = Sbs::new(I2c::from_path("/dev/i2c-0")?)?;
+//! println!("{} {}", battery.manufacturer_name()?, battery.device_name()?);
+//! println!("{}% {} mV {} mA", battery.relative_state_of_charge()?, battery.voltage()?, battery.current()?);
+//! println!("{:?}", battery.battery_status()?);
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::I2c,
+    bitflags::bitflags,
+    std::{io, os::unix::io::AsRawFd},
+};
+
+/// The slave address of a smart battery.
+pub const SBS_ADDRESS: u16 = 0x0b;
+
+/// Smart battery command codes.
+#[allow(missing_docs)]
+pub mod command {
+    pub const MANUFACTURER_ACCESS: u8 = 0x00;
+    pub const REMAINING_CAPACITY_ALARM: u8 = 0x01;
+    pub const REMAINING_TIME_ALARM: u8 = 0x02;
+    pub const BATTERY_MODE: u8 = 0x03;
+    pub const AT_RATE: u8 = 0x04;
+    pub const AT_RATE_TIME_TO_FULL: u8 = 0x05;
+    pub const AT_RATE_TIME_TO_EMPTY: u8 = 0x06;
+    pub const AT_RATE_OK: u8 = 0x07;
+    pub const TEMPERATURE: u8 = 0x08;
+    pub const VOLTAGE: u8 = 0x09;
+    pub const CURRENT: u8 = 0x0a;
+    pub const AVERAGE_CURRENT: u8 = 0x0b;
+    pub const MAX_ERROR: u8 = 0x0c;
+    pub const RELATIVE_STATE_OF_CHARGE: u8 = 0x0d;
+    pub const ABSOLUTE_STATE_OF_CHARGE: u8 = 0x0e;
+    pub const REMAINING_CAPACITY: u8 = 0x0f;
+    pub const FULL_CHARGE_CAPACITY: u8 = 0x10;
+    pub const RUN_TIME_TO_EMPTY: u8 = 0x11;
+    pub const AVERAGE_TIME_TO_EMPTY: u8 = 0x12;
+    pub const AVERAGE_TIME_TO_FULL: u8 = 0x13;
+    pub const CHARGING_CURRENT: u8 = 0x14;
+    pub const CHARGING_VOLTAGE: u8 = 0x15;
+    pub const BATTERY_STATUS: u8 = 0x16;
+    pub const CYCLE_COUNT: u8 = 0x17;
+    pub const DESIGN_CAPACITY: u8 = 0x18;
+    pub const DESIGN_VOLTAGE: u8 = 0x19;
+    pub const SPECIFICATION_INFO: u8 = 0x1a;
+    pub const MANUFACTURE_DATE: u8 = 0x1b;
+    pub const SERIAL_NUMBER: u8 = 0x1c;
+    pub const MANUFACTURER_NAME: u8 = 0x20;
+    pub const DEVICE_NAME: u8 = 0x21;
+    pub const DEVICE_CHEMISTRY: u8 = 0x22;
+    pub const MANUFACTURER_DATA: u8 = 0x23;
+}
+
+bitflags! {
+    /// `BatteryStatus` alarm and status flags.
+    ///
+    /// The low nibble holds the error code of the last command, see
+    /// `BatteryStatus::error_code`.
+    pub struct BatteryStatus: u16 {
+        /// The battery is overcharged.
+        const OVER_CHARGED_ALARM = 1 << 15;
+        /// Charging should be stopped.
+        const TERMINATE_CHARGE_ALARM = 1 << 14;
+        /// The battery temperature is too high.
+        const OVER_TEMP_ALARM = 1 << 12;
+        /// Discharging should be stopped.
+        const TERMINATE_DISCHARGE_ALARM = 1 << 11;
+        /// `RemainingCapacity` is below `RemainingCapacityAlarm`.
+        const REMAINING_CAPACITY_ALARM = 1 << 9;
+        /// `AverageTimeToEmpty` is below `RemainingTimeAlarm`.
+        const REMAINING_TIME_ALARM = 1 << 8;
+        /// The battery's calibration data is valid.
+        const INITIALIZED = 1 << 7;
+        /// The battery is discharging.
+        const DISCHARGING = 1 << 6;
+        /// The battery is fully charged.
+        const FULLY_CHARGED = 1 << 5;
+        /// The battery is fully discharged.
+        const FULLY_DISCHARGED = 1 << 4;
+        /// Mask of the error code bits.
+        const ERROR_CODE = 0x000f;
+    }
+}
+
+impl BatteryStatus {
+    /// The error code reported for the last command.
+    ///
+    /// ```rust
+    /// use i2c_linux::sbs::{BatteryStatus, ErrorCode};
+    ///
+    /// let status = BatteryStatus::from_bits_truncate(0x00c3);
+    /// assert!(status.contains(BatteryStatus::INITIALIZED | BatteryStatus::DISCHARGING));
+    /// assert!(!status.contains(BatteryStatus::FULLY_CHARGED));
+    /// assert_eq!(status.error_code(), ErrorCode::UnsupportedCommand);
+    /// ```
+    pub fn error_code(&self) -> ErrorCode {
+        match self.bits() & 0x000f {
+            0 => ErrorCode::Ok,
+            1 => ErrorCode::Busy,
+            2 => ErrorCode::ReservedCommand,
+            3 => ErrorCode::UnsupportedCommand,
+            4 => ErrorCode::AccessDenied,
+            5 => ErrorCode::OverUnderflow,
+            6 => ErrorCode::BadSize,
+            _ => ErrorCode::Unknown,
+        }
+    }
+}
+
+/// Smart battery error codes, as reported in `BatteryStatus`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The last command completed successfully.
+    Ok,
+    /// The battery is unable to process the command.
+    Busy,
+    /// The command is reserved for future use.
+    ReservedCommand,
+    /// The command is not supported by the battery.
+    UnsupportedCommand,
+    /// The command is write protected.
+    AccessDenied,
+    /// The data written was out of range.
+    OverUnderflow,
+    /// The data written was the wrong size.
+    BadSize,
+    /// An unidentified error occurred.
+    Unknown,
+}
+
+bitflags! {
+    /// `BatteryMode` capability and configuration flags.
+    pub struct BatteryMode: u16 {
+        /// Capacities are reported in 10mW units instead of mA.
+        const CAPACITY_MODE = 1 << 15;
+        /// Charging voltage and current are not broadcast to the smart charger.
+        const CHARGER_MODE = 1 << 14;
+        /// Alarm broadcasts to the host and charger are disabled.
+        const ALARM_MODE = 1 << 13;
+        /// The battery is operating in its primary role.
+        const PRIMARY_BATTERY = 1 << 9;
+        /// The internal charge controller is enabled.
+        const CHARGE_CONTROLLER_ENABLED = 1 << 8;
+        /// A conditioning cycle is requested.
+        const CONDITION_FLAG = 1 << 7;
+        /// The battery can act as either primary or secondary.
+        const PRIMARY_BATTERY_SUPPORT = 1 << 1;
+        /// The battery contains an internal charge controller.
+        const INTERNAL_CHARGE_CONTROLLER = 1 << 0;
+    }
+}
+
+/// Decodes a `ManufactureDate` value into `(year, month, day)`.
+///
+/// ```rust
+/// use i2c_linux::sbs;
+///
+/// assert_eq!(sbs::decode_date(0x4d8f), (2018, 12, 15));
+/// ```
+pub fn decode_date(value: u16) -> (u16, u8, u8) {
+    (1980 + (value >> 9), ((value >> 5) & 0x0f) as u8, (value & 0x1f) as u8)
+}
+
+/// A smart battery.
+pub struct Sbs<I> {
+    inner: I2c<I>,
+}
+
+impl<I> Sbs<I> {
+    /// Consumes the handle to return the underlying I2C bus.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying I2C bus.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying I2C bus.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
+
+impl<I: AsRawFd> Sbs<I> {
+    /// Creates a new smart battery handle at the standard address.
+    pub fn new(mut i2c: I2c<I>) -> io::Result<Self> {
+        i2c.smbus_set_slave_address(SBS_ADDRESS, false)?;
+
+        Ok(Sbs { inner: i2c })
+    }
+
+    /// Reads a word sized command.
+    pub fn read_word(&mut self, command: u8) -> io::Result<u16> {
+        self.inner.smbus_read_word_data(command)
+    }
+
+    /// Reads a block command as a string.
+    pub fn read_string(&mut self, command: u8) -> io::Result<String> {
+        let mut data = [0u8; 32];
+        let len = self.inner.smbus_read_block_data(command, &mut data)?;
+        Ok(String::from_utf8_lossy(&data[..len])
+            .trim_end_matches(&[' ', '\0'][..])
+            .into())
+    }
+
+    /// Battery mode flags.
+    pub fn battery_mode(&mut self) -> io::Result<BatteryMode> {
+        self.read_word(command::BATTERY_MODE)
+            .map(BatteryMode::from_bits_truncate)
+    }
+
+    /// Battery status and alarm flags.
+    pub fn battery_status(&mut self) -> io::Result<BatteryStatus> {
+        self.read_word(command::BATTERY_STATUS)
+            .map(BatteryStatus::from_bits_truncate)
+    }
+
+    /// Internal temperature in millidegrees Celsius.
+    pub fn temperature(&mut self) -> io::Result<i32> {
+        // Reported in units of 0.1K
+        self.read_word(command::TEMPERATURE).map(|t| t as i32 * 100 - 273_150)
+    }
+
+    /// Pack voltage in mV.
+    pub fn voltage(&mut self) -> io::Result<u16> {
+        self.read_word(command::VOLTAGE)
+    }
+
+    /// Instantaneous current in mA. Negative values indicate discharge.
+    pub fn current(&mut self) -> io::Result<i16> {
+        self.read_word(command::CURRENT).map(|c| c as i16)
+    }
+
+    /// One minute rolling average current in mA.
+    pub fn average_current(&mut self) -> io::Result<i16> {
+        self.read_word(command::AVERAGE_CURRENT).map(|c| c as i16)
+    }
+
+    /// Remaining capacity as a percentage of `FullChargeCapacity`.
+    pub fn relative_state_of_charge(&mut self) -> io::Result<u8> {
+        self.read_word(command::RELATIVE_STATE_OF_CHARGE).map(|v| v as u8)
+    }
+
+    /// Remaining capacity as a percentage of `DesignCapacity`.
+    ///
+    /// This may exceed 100%.
+    pub fn absolute_state_of_charge(&mut self) -> io::Result<u8> {
+        self.read_word(command::ABSOLUTE_STATE_OF_CHARGE).map(|v| v as u8)
+    }
+
+    /// Remaining capacity in mAh, or 10mWh if `BatteryMode::CAPACITY_MODE`
+    /// is set.
+    pub fn remaining_capacity(&mut self) -> io::Result<u16> {
+        self.read_word(command::REMAINING_CAPACITY)
+    }
+
+    /// Predicted capacity when fully charged, in mAh or 10mWh.
+    pub fn full_charge_capacity(&mut self) -> io::Result<u16> {
+        self.read_word(command::FULL_CHARGE_CAPACITY)
+    }
+
+    /// Theoretical capacity of a new pack, in mAh or 10mWh.
+    pub fn design_capacity(&mut self) -> io::Result<u16> {
+        self.read_word(command::DESIGN_CAPACITY)
+    }
+
+    /// Theoretical voltage of a new pack in mV.
+    pub fn design_voltage(&mut self) -> io::Result<u16> {
+        self.read_word(command::DESIGN_VOLTAGE)
+    }
+
+    /// Predicted remaining run time in minutes at the present discharge rate.
+    pub fn run_time_to_empty(&mut self) -> io::Result<u16> {
+        self.read_word(command::RUN_TIME_TO_EMPTY)
+    }
+
+    /// Predicted remaining time in minutes until fully charged, based on the
+    /// average current.
+    pub fn average_time_to_full(&mut self) -> io::Result<u16> {
+        self.read_word(command::AVERAGE_TIME_TO_FULL)
+    }
+
+    /// Number of charge/discharge cycles the battery has experienced.
+    pub fn cycle_count(&mut self) -> io::Result<u16> {
+        self.read_word(command::CYCLE_COUNT)
+    }
+
+    /// Manufacturing date as `(year, month, day)`.
+    pub fn manufacture_date(&mut self) -> io::Result<(u16, u8, u8)> {
+        self.read_word(command::MANUFACTURE_DATE).map(decode_date)
+    }
+
+    /// Manufacturer assigned serial number.
+    pub fn serial_number(&mut self) -> io::Result<u16> {
+        self.read_word(command::SERIAL_NUMBER)
+    }
+
+    /// Battery manufacturer's name.
+    pub fn manufacturer_name(&mut self) -> io::Result<String> {
+        self.read_string(command::MANUFACTURER_NAME)
+    }
+
+    /// Battery model name.
+    pub fn device_name(&mut self) -> io::Result<String> {
+        self.read_string(command::DEVICE_NAME)
+    }
+
+    /// Battery chemistry, such as `LION`.
+    pub fn device_chemistry(&mut self) -> io::Result<String> {
+        self.read_string(command::DEVICE_CHEMISTRY)
+    }
+}
@@ -0,0 +1,303 @@
+//! LM75 family, TMP102 and TMP117 temperature sensors.
+//!
+//! These sensors transfer their 16-bit registers most significant byte
+//! first, the reverse of the SMBus word order. Temperatures are expressed in
+//! millidegrees Celsius.
+//!
+//! The TMP102 is assumed to be in its default 12-bit (normal) mode.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{sensors::Sensor, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut sensor = Sensor::probe(I2c::from_path("/dev/i2c-1")?, 0x48)?;
+//! println!("{:?}: {} m°C", sensor.chip(), sensor.temperature()?);
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::I2c,
+    std::{
+        io,
+        os::unix::io::AsRawFd,
+        thread::sleep,
+        time::{Duration, Instant},
+    },
+};
+
+/// Sensor registers.
+#[allow(missing_docs)]
+pub mod register {
+    pub const TEMPERATURE: u8 = 0x00;
+    pub const CONFIGURATION: u8 = 0x01;
+    /// `THYST` on the LM75.
+    pub const T_LOW: u8 = 0x02;
+    /// `TOS` on the LM75.
+    pub const T_HIGH: u8 = 0x03;
+    /// TMP117 only.
+    pub const DEVICE_ID: u8 = 0x0f;
+}
+
+const LM75_SHUTDOWN: u16 = 0x01;
+const TMP102_SHUTDOWN: u16 = 0x0100;
+const TMP102_ONE_SHOT: u16 = 0x8000;
+const TMP102_RESOLUTION: u16 = 0x6000;
+const TMP117_MODE_MASK: u16 = 0x0c00;
+const TMP117_MODE_SHUTDOWN: u16 = 0x0400;
+const TMP117_MODE_ONE_SHOT: u16 = 0x0c00;
+const TMP117_DATA_READY: u16 = 0x2000;
+const TMP117_DEVICE_ID: u16 = 0x0117;
+
+const CONVERSION_POLL: Duration = Duration::from_millis(10);
+const CONVERSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Supported sensor chips.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Chip {
+    /// LM75 and compatible sensors, with an 8-bit configuration register
+    /// and 9-bit alert thresholds.
+    Lm75,
+    /// TI TMP102.
+    Tmp102,
+    /// TI TMP117.
+    Tmp117,
+}
+
+impl Chip {
+    /// Converts a temperature or threshold register to millidegrees.
+    ///
+    /// ```rust
+    /// use i2c_linux::sensors::Chip;
+    ///
+    /// assert_eq!(Chip::Lm75.decode(0x1980), 25500);
+    /// assert_eq!(Chip::Tmp102.decode(0xe700), -25000);
+    /// assert_eq!(Chip::Tmp117.decode(0x0d00), 26000);
+    /// ```
+    pub fn decode(self, raw: u16) -> i32 {
+        let raw = raw as i16 as i32;
+        match self {
+            // Left justified, with unused low bits reading as zero
+            Chip::Lm75 | Chip::Tmp102 => raw * 1000 / 256,
+            Chip::Tmp117 => raw * 1000 / 128,
+        }
+    }
+
+    /// Converts millidegrees to a threshold register, truncated to the
+    /// chip's resolution and saturated to its range.
+    ///
+    /// ```rust
+    /// use i2c_linux::sensors::Chip;
+    ///
+    /// assert_eq!(Chip::Lm75.encode(80000), 0x5000);
+    /// assert_eq!(Chip::Lm75.encode(25700), 0x1980);
+    /// assert_eq!(Chip::Tmp117.encode(-40000), 0xec00);
+    /// ```
+    pub fn encode(self, millidegrees: i32) -> u16 {
+        let (scale, mask) = match self {
+            Chip::Lm75 => (256, 0xff80),
+            Chip::Tmp102 => (256, 0xfff0),
+            Chip::Tmp117 => (128, 0xffff),
+        };
+        let raw = (millidegrees as i64 * scale / 1000).clamp(i16::MIN as i64, i16::MAX as i64);
+        raw as i16 as u16 & mask
+    }
+
+    /// Whether the chip supports one-shot conversions.
+    pub fn has_one_shot(self) -> bool {
+        !matches!(self, Chip::Lm75)
+    }
+}
+
+/// Identifies the sensor at `address`.
+///
+/// The TMP117 is recognized by its device ID. The TMP102 and LM75 have no
+/// identification registers, so they are recognized by bits of their
+/// configuration and threshold registers that always read as fixed values,
+/// which other devices may happen to match. The slave address of `i2c` is
+/// left pointing at `address`.
+pub fn detect<I: AsRawFd>(i2c: &mut I2c<I>, address: u16) -> io::Result<Option<Chip>> {
+    i2c.smbus_set_slave_address(address, false)?;
+
+    if let Ok(id) = read_register(i2c, register::DEVICE_ID) {
+        if id & 0x0fff == TMP117_DEVICE_ID {
+            return Ok(Some(Chip::Tmp117))
+        }
+    }
+
+    let config = read_register(i2c, register::CONFIGURATION)?;
+    // Read-only resolution bits, and unused low bits
+    if config & TMP102_RESOLUTION == TMP102_RESOLUTION && config & 0x000f == 0 {
+        return Ok(Some(Chip::Tmp102))
+    }
+
+    let config = i2c.smbus_read_byte_data(register::CONFIGURATION)?;
+    let t_low = read_register(i2c, register::T_LOW)?;
+    let t_high = read_register(i2c, register::T_HIGH)?;
+    // Reserved configuration bits, and unused low threshold bits
+    if config & 0xe0 == 0 && t_low & 0x007f == 0 && t_high & 0x007f == 0 {
+        return Ok(Some(Chip::Lm75))
+    }
+
+    Ok(None)
+}
+
+fn read_register<I: AsRawFd>(i2c: &mut I2c<I>, register: u8) -> io::Result<u16> {
+    i2c.smbus_read_word_data(register).map(u16::swap_bytes)
+}
+
+fn write_register<I: AsRawFd>(i2c: &mut I2c<I>, register: u8, value: u16) -> io::Result<()> {
+    i2c.smbus_write_word_data(register, value.swap_bytes())
+}
+
+/// A temperature sensor.
+pub struct Sensor<I> {
+    inner: I2c<I>,
+    chip: Chip,
+}
+
+impl<I: AsRawFd> Sensor<I> {
+    /// Creates a handle for the sensor at `address`.
+    pub fn new(mut i2c: I2c<I>, chip: Chip, address: u16) -> io::Result<Self> {
+        i2c.smbus_set_slave_address(address, false)?;
+
+        Ok(Sensor { inner: i2c, chip })
+    }
+
+    /// Creates a handle for the sensor at `address`, identifying it with
+    /// [detect].
+    ///
+    /// Fails with `NotFound` if the sensor is not recognized.
+    pub fn probe(mut i2c: I2c<I>, address: u16) -> io::Result<Self> {
+        match detect(&mut i2c, address)? {
+            Some(chip) => Ok(Sensor { inner: i2c, chip }),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "unrecognized temperature sensor",
+            )),
+        }
+    }
+
+    /// Reads the most recent temperature conversion.
+    pub fn temperature(&mut self) -> io::Result<i32> {
+        read_register(&mut self.inner, register::TEMPERATURE).map(|raw| self.chip.decode(raw))
+    }
+
+    /// Reads the configuration register.
+    pub fn config(&mut self) -> io::Result<u16> {
+        match self.chip {
+            Chip::Lm75 => self.inner.smbus_read_byte_data(register::CONFIGURATION).map(u16::from),
+            Chip::Tmp102 | Chip::Tmp117 => read_register(&mut self.inner, register::CONFIGURATION),
+        }
+    }
+
+    /// Writes the configuration register.
+    pub fn set_config(&mut self, config: u16) -> io::Result<()> {
+        match self.chip {
+            Chip::Lm75 => self.inner.smbus_write_byte_data(register::CONFIGURATION, config as u8),
+            Chip::Tmp102 | Chip::Tmp117 => write_register(&mut self.inner, register::CONFIGURATION, config),
+        }
+    }
+
+    /// Whether the sensor is shut down between conversions.
+    pub fn is_shutdown(&mut self) -> io::Result<bool> {
+        let config = self.config()?;
+        Ok(match self.chip {
+            Chip::Lm75 => config & LM75_SHUTDOWN != 0,
+            Chip::Tmp102 => config & TMP102_SHUTDOWN != 0,
+            Chip::Tmp117 => config & TMP117_MODE_MASK == TMP117_MODE_SHUTDOWN,
+        })
+    }
+
+    /// Shuts the sensor down, or resumes continuous conversions.
+    pub fn set_shutdown(&mut self, shutdown: bool) -> io::Result<()> {
+        let config = self.config()?;
+        let config = match self.chip {
+            Chip::Lm75 => set_bits(config, LM75_SHUTDOWN, shutdown),
+            Chip::Tmp102 => set_bits(config, TMP102_SHUTDOWN, shutdown),
+            Chip::Tmp117 => config & !TMP117_MODE_MASK | if shutdown { TMP117_MODE_SHUTDOWN } else { 0 },
+        };
+        self.set_config(config)
+    }
+
+    /// Performs a single conversion, leaving the sensor shut down, and
+    /// returns its result.
+    pub fn one_shot(&mut self) -> io::Result<i32> {
+        let (config, done) = match self.chip {
+            Chip::Lm75 =>
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "sensor does not support one-shot conversions",
+                )),
+            // The one-shot bit reads back as set once the conversion completes
+            Chip::Tmp102 => (self.config()? | TMP102_SHUTDOWN | TMP102_ONE_SHOT, TMP102_ONE_SHOT),
+            Chip::Tmp117 => (
+                self.config()? & !TMP117_MODE_MASK | TMP117_MODE_ONE_SHOT,
+                TMP117_DATA_READY,
+            ),
+        };
+        self.set_config(config)?;
+
+        let deadline = Instant::now() + CONVERSION_TIMEOUT;
+        loop {
+            sleep(CONVERSION_POLL);
+            if self.config()? & done != 0 {
+                break self.temperature()
+            }
+            if Instant::now() >= deadline {
+                break Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "temperature conversion timed out",
+                ))
+            }
+        }
+    }
+
+    /// Reads the low and high alert thresholds, in millidegrees.
+    ///
+    /// On the LM75 these are `THYST` and `TOS`.
+    pub fn thresholds(&mut self) -> io::Result<(i32, i32)> {
+        let low = read_register(&mut self.inner, register::T_LOW)?;
+        let high = read_register(&mut self.inner, register::T_HIGH)?;
+        Ok((self.chip.decode(low), self.chip.decode(high)))
+    }
+
+    /// Sets the low and high alert thresholds, in millidegrees.
+    pub fn set_thresholds(&mut self, low: i32, high: i32) -> io::Result<()> {
+        write_register(&mut self.inner, register::T_LOW, self.chip.encode(low))?;
+        write_register(&mut self.inner, register::T_HIGH, self.chip.encode(high))
+    }
+}
+
+impl<I> Sensor<I> {
+    /// The sensor chip.
+    pub fn chip(&self) -> Chip {
+        self.chip
+    }
+
+    /// Returns the underlying adapter.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying adapter.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying adapter.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
+
+fn set_bits(value: u16, bits: u16, set: bool) -> u16 {
+    if set {
+        value | bits
+    } else {
+        value & !bits
+    }
+}
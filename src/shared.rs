@@ -0,0 +1,182 @@
+//! Sharing a single adapter between threads.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use {i2c_linux::{I2c, SharedBus}, std::thread};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let bus = SharedBus::new(I2c::from_path("/dev/i2c-1")?);
+//! let sensor = bus.device(0x48, false);
+//! let eeprom = bus.device(0x50, false);
+//! let worker = thread::spawn(move || sensor.smbus_read_word_data(0x00));
+//! let byte = eeprom.smbus_read_byte_data(0x00)?;
+//! let word = worker.join().unwrap()?;
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::I2c,
+    std::{
+        io,
+        os::unix::io::AsRawFd,
+        sync::{Arc, Mutex, MutexGuard},
+    },
+};
+
+/// An adapter shared between threads.
+///
+/// Cloning the bus produces another handle to the same adapter.
+pub struct SharedBus<I> {
+    inner: Arc<Mutex<I2c<I>>>,
+}
+
+impl<I> Clone for SharedBus<I> {
+    fn clone(&self) -> Self {
+        SharedBus {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<I> SharedBus<I> {
+    /// Shares an adapter.
+    pub fn new(i2c: I2c<I>) -> Self {
+        SharedBus {
+            inner: Arc::new(Mutex::new(i2c)),
+        }
+    }
+
+    /// Creates a handle to the device at `address`.
+    pub fn device(&self, address: u16, tenbit: bool) -> BusDevice<I> {
+        BusDevice {
+            bus: self.inner.clone(),
+            address,
+            tenbit,
+        }
+    }
+
+    /// Locks the adapter for exclusive use, such as for operations that
+    /// span several devices.
+    pub fn lock(&self) -> MutexGuard<'_, I2c<I>> {
+        lock(&self.inner)
+    }
+
+    /// Returns the adapter if no other bus or device handles remain.
+    pub fn try_into_inner(self) -> Result<I2c<I>, Self> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(mutex) => Ok(mutex.into_inner().unwrap_or_else(|e| e.into_inner())),
+            Err(inner) => Err(SharedBus { inner }),
+        }
+    }
+}
+
+// A panic while the adapter was locked does not leave `I2c` inconsistent, as
+// its slave address is only updated once the kernel accepts it.
+fn lock<I>(mutex: &Mutex<I2c<I>>) -> MutexGuard<'_, I2c<I>> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A handle to a single device on a [SharedBus].
+pub struct BusDevice<I> {
+    bus: Arc<Mutex<I2c<I>>>,
+    address: u16,
+    tenbit: bool,
+}
+
+impl<I> Clone for BusDevice<I> {
+    fn clone(&self) -> Self {
+        BusDevice {
+            bus: self.bus.clone(),
+            address: self.address,
+            tenbit: self.tenbit,
+        }
+    }
+}
+
+impl<I> BusDevice<I> {
+    /// The slave address of the device.
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// Whether the device uses a 10-bit address.
+    pub fn is_tenbit(&self) -> bool {
+        self.tenbit
+    }
+
+    /// Returns a handle to the bus the device is on.
+    pub fn bus(&self) -> SharedBus<I> {
+        SharedBus {
+            inner: self.bus.clone(),
+        }
+    }
+}
+
+impl<I: AsRawFd> BusDevice<I> {
+    /// Locks the adapter, addresses the device and runs `f`.
+    ///
+    /// The slave address is only set when another device was addressed
+    /// last. The adapter remains locked for the duration of `f`, so no other
+    /// handle can interleave transfers with it.
+    pub fn with<R, F: FnOnce(&mut I2c<I>) -> io::Result<R>>(&self, f: F) -> io::Result<R> {
+        let mut i2c = lock(&self.bus);
+        if i2c.address != Some(self.address) || i2c.address_10bit != self.tenbit {
+            i2c.smbus_set_slave_address(self.address, self.tenbit)?;
+        }
+
+        f(&mut i2c)
+    }
+
+    /// See [I2c::smbus_read_byte].
+    pub fn smbus_read_byte(&self) -> io::Result<u8> {
+        self.with(|i2c| i2c.smbus_read_byte())
+    }
+
+    /// See [I2c::smbus_write_byte].
+    pub fn smbus_write_byte(&self, value: u8) -> io::Result<()> {
+        self.with(|i2c| i2c.smbus_write_byte(value))
+    }
+
+    /// See [I2c::smbus_read_byte_data].
+    pub fn smbus_read_byte_data(&self, command: u8) -> io::Result<u8> {
+        self.with(|i2c| i2c.smbus_read_byte_data(command))
+    }
+
+    /// See [I2c::smbus_write_byte_data].
+    pub fn smbus_write_byte_data(&self, command: u8, value: u8) -> io::Result<()> {
+        self.with(|i2c| i2c.smbus_write_byte_data(command, value))
+    }
+
+    /// See [I2c::smbus_read_word_data].
+    pub fn smbus_read_word_data(&self, command: u8) -> io::Result<u16> {
+        self.with(|i2c| i2c.smbus_read_word_data(command))
+    }
+
+    /// See [I2c::smbus_write_word_data].
+    pub fn smbus_write_word_data(&self, command: u8, value: u16) -> io::Result<()> {
+        self.with(|i2c| i2c.smbus_write_word_data(command, value))
+    }
+
+    /// See [I2c::smbus_read_block_data].
+    pub fn smbus_read_block_data(&self, command: u8, value: &mut [u8]) -> io::Result<usize> {
+        self.with(|i2c| i2c.smbus_read_block_data(command, value))
+    }
+
+    /// See [I2c::smbus_write_block_data].
+    pub fn smbus_write_block_data(&self, command: u8, value: &[u8]) -> io::Result<()> {
+        self.with(|i2c| i2c.smbus_write_block_data(command, value))
+    }
+
+    /// See [I2c::i2c_read_block_data].
+    pub fn i2c_read_block_data(&self, command: u8, value: &mut [u8]) -> io::Result<usize> {
+        self.with(|i2c| i2c.i2c_read_block_data(command, value))
+    }
+
+    /// See [I2c::i2c_write_block_data].
+    pub fn i2c_write_block_data(&self, command: u8, value: &[u8]) -> io::Result<()> {
+        self.with(|i2c| i2c.i2c_write_block_data(command, value))
+    }
+}
@@ -0,0 +1,432 @@
+//! DDR SPD (Serial Presence Detect) EEPROM access and decoding.
+//!
+//! DDR4 modules use an EE1004 compatible EEPROM, which exposes 512 bytes as two
+//! 256 byte pages selected by writing to one of the page select addresses.
+//! DDR5 modules use an SPD5118 hub, which exposes 1024 bytes as eight 128 byte
+//! pages selected through its legacy mode configuration register.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{spd, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut i2c = I2c::from_path("/dev/i2c-0")?;
+//! let data = spd::read_eeprom(&mut i2c, 0)?;
+//! let info = spd::decode(&data)?;
+//! println!("{:?} {} MiB {}", info.memory_type, info.capacity_mib, info.part_number);
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{is_nack, I2c},
+    std::{io, os::unix::io::AsRawFd},
+};
+
+/// The slave address of the SPD EEPROM in the first DIMM slot.
+///
+/// Slots `0..8` are found at `SPD_ADDRESS + slot`.
+pub const SPD_ADDRESS: u16 = 0x50;
+
+/// EE1004 "set page address" addresses, used to select page 0 or 1.
+///
+/// Only the first one acknowledges reads, and only while page 0 is selected.
+pub const EE1004_PAGE_ADDRESS: [u16; 2] = [0x36, 0x37];
+
+/// Size of an EE1004 (DDR4) SPD EEPROM.
+pub const EE1004_SIZE: usize = 512;
+
+/// Size of an SPD5118 (DDR5) hub's NVM.
+pub const SPD5118_SIZE: usize = 1024;
+
+const EE1004_PAGE_SIZE: usize = 256;
+const SPD5118_PAGE_SIZE: usize = 128;
+const SPD5118_REG_TYPE: u8 = 0x00;
+const SPD5118_REG_LEGACY_MODE: u8 = 0x0b;
+const SPD5118_LEGACY_PAGE_MASK: u8 = 0x07;
+const SPD5118_EEPROM_BASE: u8 = 0x80;
+const SPD5118_DEVICE_TYPE: [u8; 2] = [0x51, 0x18];
+const READ_CHUNK: usize = 32;
+
+/// DRAM device type, as reported in SPD byte 2.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MemoryType {
+    /// DDR3 SDRAM
+    Ddr3,
+    /// DDR4 SDRAM
+    Ddr4,
+    /// DDR4E SDRAM
+    Ddr4E,
+    /// LPDDR4 SDRAM
+    LpDdr4,
+    /// DDR5 SDRAM
+    Ddr5,
+    /// LPDDR5 SDRAM
+    LpDdr5,
+    /// An unrecognized device type.
+    Other(u8),
+}
+
+impl From<u8> for MemoryType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x0b => MemoryType::Ddr3,
+            0x0c => MemoryType::Ddr4,
+            0x0e => MemoryType::Ddr4E,
+            0x10 => MemoryType::LpDdr4,
+            0x12 => MemoryType::Ddr5,
+            0x13 => MemoryType::LpDdr5,
+            value => MemoryType::Other(value),
+        }
+    }
+}
+
+/// Base module type, as reported in the low nibble of SPD byte 3.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ModuleType {
+    /// Registered DIMM
+    RDimm,
+    /// Unbuffered DIMM
+    UDimm,
+    /// Unbuffered SO-DIMM
+    SoDimm,
+    /// Load Reduced DIMM
+    LrDimm,
+    /// An unrecognized module type.
+    Other(u8),
+}
+
+impl From<u8> for ModuleType {
+    fn from(value: u8) -> Self {
+        match value & 0x0f {
+            0x01 => ModuleType::RDimm,
+            0x02 => ModuleType::UDimm,
+            0x03 => ModuleType::SoDimm,
+            0x04 => ModuleType::LrDimm,
+            value => ModuleType::Other(value),
+        }
+    }
+}
+
+/// A JEP-106 manufacturer identification code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct JedecId {
+    /// The number of continuation codes (`0x7f`) preceding the ID, starting
+    /// at 0 for bank 1.
+    pub bank: u8,
+    /// The manufacturer code within its bank, with the parity bit removed.
+    pub id: u8,
+}
+
+impl JedecId {
+    /// Decodes the two byte SPD representation of a manufacturer ID.
+    pub fn from_spd(bytes: [u8; 2]) -> Self {
+        JedecId {
+            bank: bytes[0] & 0x7f,
+            id: bytes[1] & 0x7f,
+        }
+    }
+}
+
+/// Key fields decoded from an SPD EEPROM image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpdInfo {
+    /// The DRAM generation.
+    pub memory_type: MemoryType,
+    /// The module form factor.
+    pub module_type: ModuleType,
+    /// Total module capacity in MiB.
+    pub capacity_mib: u64,
+    /// Minimum clock cycle time (tCKAVGmin) in picoseconds.
+    pub min_cycle_time_ps: u32,
+    /// Maximum data rate in MT/s, snapped to the nearest JEDEC speed bin.
+    pub speed_mts: u32,
+    /// Module manufacturer.
+    pub manufacturer: JedecId,
+    /// Module part number, with trailing padding removed.
+    pub part_number: String,
+    /// Module serial number.
+    pub serial: u32,
+    /// Whether the CRC protecting the base configuration section matches.
+    pub crc_valid: bool,
+}
+
+/// Detects the EEPROM type of the DIMM in `slot` and reads its entire contents.
+///
+/// The returned buffer is 512 bytes for DDR4 modules and 1024 bytes for DDR5
+/// modules. The slave address of `i2c` is left pointing at the SPD EEPROM.
+pub fn read_eeprom<I: AsRawFd>(i2c: &mut I2c<I>, slot: u8) -> io::Result<Vec<u8>> {
+    let address = slot_address(slot)?;
+    i2c.smbus_set_slave_address(address, false)?;
+    if is_spd5118(i2c)? {
+        let mut data = vec![0u8; SPD5118_SIZE];
+        read_spd5118(i2c, slot, &mut data)?;
+        Ok(data)
+    } else {
+        let mut data = vec![0u8; EE1004_SIZE];
+        read_ee1004(i2c, slot, &mut data)?;
+        Ok(data)
+    }
+}
+
+/// Reads up to 512 bytes from a DDR4 EE1004 SPD EEPROM, switching pages as
+/// necessary.
+///
+/// Page 0 is selected again before returning, even on failure, so that other
+/// readers find the EEPROM in its default state. Returns the amount of data
+/// read.
+pub fn read_ee1004<I: AsRawFd>(i2c: &mut I2c<I>, slot: u8, data: &mut [u8]) -> io::Result<usize> {
+    let address = slot_address(slot)?;
+    let len = data.len().min(EE1004_SIZE);
+    let res = data[..len]
+        .chunks_mut(EE1004_PAGE_SIZE)
+        .enumerate()
+        .try_for_each(|(page, data)| {
+            ee1004_set_page(i2c, page)?;
+            i2c.smbus_set_slave_address(address, false)?;
+            read_chunks(i2c, 0, data)
+        });
+
+    let restored = ee1004_set_page(i2c, 0).and_then(|()| i2c.smbus_set_slave_address(address, false));
+    res.and(restored).map(|()| len)
+}
+
+/// Reads up to 1024 bytes from a DDR5 SPD5118 hub's NVM, switching pages as
+/// necessary.
+///
+/// The hub must be configured for 1-byte legacy addressing, which is its
+/// default state. Returns the amount of data read.
+pub fn read_spd5118<I: AsRawFd>(i2c: &mut I2c<I>, slot: u8, data: &mut [u8]) -> io::Result<usize> {
+    let address = slot_address(slot)?;
+    i2c.smbus_set_slave_address(address, false)?;
+    let len = data.len().min(SPD5118_SIZE);
+    for (page, data) in data[..len].chunks_mut(SPD5118_PAGE_SIZE).enumerate() {
+        let mode = i2c.smbus_read_byte_data(SPD5118_REG_LEGACY_MODE)?;
+        i2c.smbus_write_byte_data(SPD5118_REG_LEGACY_MODE, (mode & !SPD5118_LEGACY_PAGE_MASK) | page as u8)?;
+        read_chunks(i2c, SPD5118_EEPROM_BASE, data)?;
+    }
+
+    Ok(len)
+}
+
+/// Decodes the key fields of a DDR4 or DDR5 SPD image.
+///
+/// # Example
+///
+/// ```rust
+/// use i2c_linux::spd::{self, JedecId, MemoryType, ModuleType, SpdInfo};
+///
+/// // Synthetic images laid out per JEDEC, not captures from real modules
+/// let data = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/spd/synthetic-ddr4-3200-sodimm.bin"));
+/// assert_eq!(spd::crc16(&data[..126]), 0x7f5b);
+/// assert_eq!(spd::decode(data).unwrap(), SpdInfo {
+///     memory_type: MemoryType::Ddr4,
+///     module_type: ModuleType::SoDimm,
+///     capacity_mib: 16 * 1024,
+///     min_cycle_time_ps: 625,
+///     speed_mts: 3200,
+///     manufacturer: JedecId { bank: 0, id: 0x4e },
+///     part_number: "M471A2K43EB1-CWE".into(),
+///     serial: 0x3a5b01c7,
+///     crc_valid: true,
+/// });
+///
+/// let data = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/spd/synthetic-ddr5-4800-udimm.bin"));
+/// assert_eq!(spd::crc16(&data[..510]), 0x2f5e);
+/// assert_eq!(spd::decode(data).unwrap(), SpdInfo {
+///     memory_type: MemoryType::Ddr5,
+///     module_type: ModuleType::UDimm,
+///     capacity_mib: 16 * 1024,
+///     min_cycle_time_ps: 416,
+///     speed_mts: 4800,
+///     manufacturer: JedecId { bank: 0, id: 0x2c },
+///     part_number: "MTC8C1084S1UC48BA1".into(),
+///     serial: 0x4e219a03,
+///     crc_valid: true,
+/// });
+///
+/// // A corrupted base configuration section still decodes
+/// let mut corrupt = data.to_vec();
+/// corrupt[509] ^= 0x01;
+/// assert!(!spd::decode(&corrupt).unwrap().crc_valid);
+/// ```
+pub fn decode(data: &[u8]) -> io::Result<SpdInfo> {
+    match data.get(2).cloned().map(MemoryType::from) {
+        Some(MemoryType::Ddr4) | Some(MemoryType::Ddr4E) => decode_ddr4(data),
+        Some(MemoryType::Ddr5) => decode_ddr5(data),
+        Some(ty) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported SPD memory type {:?}", ty),
+        )),
+        None => Err(truncated()),
+    }
+}
+
+/// Computes the CRC-16 (polynomial 0x1021, initial value 0) used to protect
+/// SPD data.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn decode_ddr4(data: &[u8]) -> io::Result<SpdInfo> {
+    if data.len() < EE1004_SIZE {
+        return Err(truncated())
+    }
+
+    let density_mbit = match data[4] & 0x0f {
+        density @ 0..=7 => 256u64 << density,
+        8 => 12 * 1024,
+        9 => 24 * 1024,
+        density => return Err(invalid_field("SDRAM density", density)),
+    };
+    let die_count = if data[6] & 0x03 == 0x02 {
+        ((data[6] >> 4) & 0x07) as u64 + 1
+    } else {
+        1
+    };
+    let ranks = ((data[12] >> 3) & 0x07) as u64 + 1;
+    let device_width = 4u64 << (data[12] & 0x07);
+    let bus_width = 8u64 << (data[13] & 0x07);
+    let capacity_mib = density_mbit / 8 * bus_width / device_width * ranks * die_count;
+
+    let min_cycle_time_ps = (data[18] as i32 * 125 + data[125] as i8 as i32).max(1) as u32;
+
+    Ok(SpdInfo {
+        memory_type: data[2].into(),
+        module_type: data[3].into(),
+        capacity_mib,
+        min_cycle_time_ps,
+        speed_mts: speed_bin(min_cycle_time_ps, 800, 3),
+        manufacturer: JedecId::from_spd([data[320], data[321]]),
+        part_number: spd_string(&data[329..349]),
+        serial: u32::from_be_bytes([data[325], data[326], data[327], data[328]]),
+        crc_valid: crc16(&data[..126]) == u16::from_le_bytes([data[126], data[127]]),
+    })
+}
+
+fn decode_ddr5(data: &[u8]) -> io::Result<SpdInfo> {
+    if data.len() < SPD5118_SIZE {
+        return Err(truncated())
+    }
+
+    let density_mbit = match data[4] & 0x1f {
+        1 => 4 * 1024,
+        2 => 8 * 1024,
+        3 => 12 * 1024,
+        4 => 16 * 1024,
+        5 => 24 * 1024,
+        6 => 32 * 1024,
+        7 => 48 * 1024,
+        8 => 64 * 1024,
+        density => return Err(invalid_field("SDRAM density", density)),
+    };
+    let die_count = match data[4] >> 5 {
+        0 => 1,
+        dies @ 2..=5 => 1u64 << (dies - 1),
+        dies => return Err(invalid_field("die per package", dies)),
+    };
+    let device_width = 4u64 << ((data[6] >> 5) & 0x03);
+    let ranks = ((data[234] >> 3) & 0x07) as u64 + 1;
+    let bus_width = 8u64 << (data[235] & 0x07);
+    let channels = ((data[235] >> 5) & 0x03) as u64 + 1;
+    let capacity_mib = channels * bus_width / device_width * die_count * density_mbit / 8 * ranks;
+
+    let min_cycle_time_ps = (u16::from_le_bytes([data[20], data[21]]) as u32).max(1);
+
+    Ok(SpdInfo {
+        memory_type: data[2].into(),
+        module_type: data[3].into(),
+        capacity_mib,
+        min_cycle_time_ps,
+        speed_mts: speed_bin(min_cycle_time_ps, 400, 1),
+        manufacturer: JedecId::from_spd([data[512], data[513]]),
+        part_number: spd_string(&data[521..551]),
+        serial: u32::from_be_bytes([data[517], data[518], data[519], data[520]]),
+        crc_valid: crc16(&data[..510]) == u16::from_le_bytes([data[510], data[511]]),
+    })
+}
+
+/// Converts a cycle time to a data rate, rounded to the nearest multiple of
+/// `step_num / step_den` MT/s.
+fn speed_bin(cycle_time_ps: u32, step_num: u64, step_den: u64) -> u32 {
+    let rate = 2_000_000 * step_den;
+    let steps = (rate + cycle_time_ps as u64 * step_num / 2) / (cycle_time_ps as u64 * step_num);
+    (steps * step_num / step_den) as u32
+}
+
+fn spd_string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).trim_end_matches(&[' ', '\0'][..]).into()
+}
+
+fn slot_address(slot: u8) -> io::Result<u16> {
+    if slot < 8 {
+        Ok(SPD_ADDRESS + slot as u16)
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "SPD slot out of range"))
+    }
+}
+
+fn is_spd5118<I: AsRawFd>(i2c: &mut I2c<I>) -> io::Result<bool> {
+    let mut device_type = [0u8; 2];
+    for (i, byte) in device_type.iter_mut().enumerate() {
+        *byte = i2c.smbus_read_byte_data(SPD5118_REG_TYPE + i as u8)?;
+    }
+    Ok(device_type == SPD5118_DEVICE_TYPE)
+}
+
+fn ee1004_set_page<I: AsRawFd>(i2c: &mut I2c<I>, page: usize) -> io::Result<()> {
+    i2c.smbus_set_slave_address(EE1004_PAGE_ADDRESS[page], false)?;
+    match i2c.smbus_write_byte(0) {
+        Ok(()) => Ok(()),
+        // Some modules switch pages without acknowledging the command, so
+        // check which page is currently selected before giving up.
+        Err(e) => match ee1004_get_page(i2c) {
+            Ok(current) if current == page => Ok(()),
+            _ => Err(e),
+        },
+    }
+}
+
+fn ee1004_get_page<I: AsRawFd>(i2c: &mut I2c<I>) -> io::Result<usize> {
+    // Reads from the first page address are acknowledged only while page 0
+    // is selected
+    i2c.smbus_set_slave_address(EE1004_PAGE_ADDRESS[0], false)?;
+    match i2c.smbus_read_byte() {
+        Ok(_) => Ok(0),
+        Err(ref e) if is_nack(e) => Ok(1),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_chunks<I: AsRawFd>(i2c: &mut I2c<I>, base: u8, data: &mut [u8]) -> io::Result<()> {
+    for (i, chunk) in data.chunks_mut(READ_CHUNK).enumerate() {
+        let len = chunk.len();
+        let read = i2c.i2c_read_block_data(base + (i * READ_CHUNK) as u8, chunk)?;
+        if read < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short SPD read"))
+        }
+    }
+
+    Ok(())
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "SPD data truncated")
+}
+
+fn invalid_field(field: &str, value: u8) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid SPD {} value {:#04x}", field, value),
+    )
+}
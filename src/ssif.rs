@@ -0,0 +1,369 @@
+//! IPMI SMBus System Interface (SSIF) client.
+//!
+//! Requests are sent to the BMC with SMBus block writes and responses are
+//! collected with block reads. Messages that do not fit in a single 32 byte
+//! block are split into multi-part transactions, provided that the BMC
+//! advertises support for them through [Ssif::negotiate].
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::{ssif::{netfn, Ssif}, I2c};
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut ssif = Ssif::new(I2c::from_path("/dev/i2c-0")?, 0x10)?;
+//! ssif.negotiate()?;
+//! // Get Device ID
+//! let response = ssif.command(netfn::APP, 0, 0x01, &[])?;
+//! println!("{:?}", response);
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{is_nack, Functionality, I2c},
+    std::{
+        io,
+        os::unix::io::AsRawFd,
+        thread::sleep,
+        time::{Duration, Instant},
+    },
+};
+
+/// SSIF SMBus commands.
+#[allow(missing_docs)]
+pub mod command {
+    pub const SINGLE_PART_WRITE: u8 = 0x02;
+    pub const SINGLE_PART_READ: u8 = 0x03;
+    pub const MULTI_PART_WRITE_START: u8 = 0x06;
+    pub const MULTI_PART_WRITE_MIDDLE: u8 = 0x07;
+    pub const MULTI_PART_WRITE_END: u8 = 0x08;
+    pub const MULTI_PART_READ_MIDDLE: u8 = 0x09;
+}
+
+/// IPMI network function codes for requests.
+#[allow(missing_docs)]
+pub mod netfn {
+    pub const CHASSIS: u8 = 0x00;
+    pub const BRIDGE: u8 = 0x02;
+    pub const SENSOR_EVENT: u8 = 0x04;
+    pub const APP: u8 = 0x06;
+    pub const FIRMWARE: u8 = 0x08;
+    pub const STORAGE: u8 = 0x0a;
+    pub const TRANSPORT: u8 = 0x0c;
+}
+
+const BLOCK_LEN: usize = 32;
+const MULTI_PART_START: [u8; 2] = [0x00, 0x01];
+const MULTI_PART_LAST_BLOCK: u8 = 0xff;
+const MAX_RESPONSE_LEN: usize = 0x1000;
+
+const GET_SYSTEM_INTERFACE_CAPABILITIES: u8 = 0x57;
+const SYSTEM_INTERFACE_SSIF: u8 = 0x00;
+
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The multi-part transactions supported by a BMC.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TransactionSupport {
+    /// Only single-part reads and writes.
+    SinglePart,
+    /// Multi-part transactions made of a start and an end part.
+    StartEnd,
+    /// Multi-part transactions with any number of middle parts.
+    StartMiddleEnd,
+}
+
+/// The SSIF capabilities of a BMC.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Capabilities {
+    /// The SSIF version.
+    pub version: u8,
+    /// Whether the BMC supports SMBus PEC.
+    pub pec_supported: bool,
+    /// The supported multi-part transactions.
+    pub transaction_support: TransactionSupport,
+    /// The largest request message, including the network function and
+    /// command bytes.
+    pub max_request_len: usize,
+    /// The largest response message, including the network function, command
+    /// and completion code bytes.
+    pub max_response_len: usize,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            version: 0,
+            pec_supported: false,
+            transaction_support: TransactionSupport::SinglePart,
+            max_request_len: BLOCK_LEN,
+            max_response_len: BLOCK_LEN,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Parses the response data of a Get System Interface Capabilities
+    /// command, following the completion code.
+    ///
+    /// ```rust
+    /// use i2c_linux::ssif::{Capabilities, TransactionSupport};
+    ///
+    /// let caps = Capabilities::from_response(&[0x00, 0x88, 0x40, 0xff]).unwrap();
+    /// assert_eq!(caps.transaction_support, TransactionSupport::StartMiddleEnd);
+    /// assert!(caps.pec_supported);
+    /// assert_eq!((caps.max_request_len, caps.max_response_len), (0x40, 0xff));
+    /// ```
+    pub fn from_response(data: &[u8]) -> io::Result<Self> {
+        match *data {
+            [_, flags, max_request_len, max_response_len, ..] => Ok(Capabilities {
+                version: flags & 0x07,
+                pec_supported: flags & 0x08 != 0,
+                transaction_support: match flags >> 6 {
+                    0 => TransactionSupport::SinglePart,
+                    1 => TransactionSupport::StartEnd,
+                    _ => TransactionSupport::StartMiddleEnd,
+                },
+                max_request_len: max_request_len as usize,
+                max_response_len: max_response_len as usize,
+            }),
+            _ => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated SSIF capabilities",
+            )),
+        }
+    }
+}
+
+/// A response message from the BMC.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Response {
+    /// The response network function.
+    pub netfn: u8,
+    /// The logical unit number.
+    pub lun: u8,
+    /// The command the response belongs to.
+    pub command: u8,
+    /// The completion code.
+    pub completion_code: u8,
+    /// The response data, following the completion code.
+    pub data: Vec<u8>,
+}
+
+impl Response {
+    /// Parses a response message.
+    ///
+    /// ```rust
+    /// use i2c_linux::ssif::Response;
+    ///
+    /// let response = Response::from_bytes(&[0x1c, 0x01, 0x00, 0x20]).unwrap();
+    /// assert_eq!((response.netfn, response.lun, response.command), (0x07, 0, 0x01));
+    /// assert_eq!(response.completion_code, 0);
+    /// assert_eq!(response.data, [0x20]);
+    /// ```
+    pub fn from_bytes(message: &[u8]) -> io::Result<Self> {
+        match *message {
+            [netfn_lun, command, completion_code, ref data @ ..] => Ok(Response {
+                netfn: netfn_lun >> 2,
+                lun: netfn_lun & 0x03,
+                command,
+                completion_code,
+                data: data.to_vec(),
+            }),
+            _ => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated SSIF response")),
+        }
+    }
+}
+
+/// An IPMI SSIF client.
+pub struct Ssif<I> {
+    inner: I2c<I>,
+    capabilities: Capabilities,
+    retry_interval: Duration,
+    timeout: Duration,
+}
+
+impl<I: AsRawFd> Ssif<I> {
+    /// Creates a client for the BMC at `address`, assuming only single-part
+    /// transactions until [negotiate](Self::negotiate) is called.
+    pub fn new(mut i2c: I2c<I>, address: u16) -> io::Result<Self> {
+        i2c.smbus_set_slave_address(address, false)?;
+
+        Ok(Ssif {
+            inner: i2c,
+            capabilities: Capabilities::default(),
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Queries the BMC's SSIF capabilities and adopts them, enabling PEC if
+    /// both the BMC and the adapter support it.
+    ///
+    /// A BMC that does not implement the query is assumed to only support
+    /// single-part transactions.
+    pub fn negotiate(&mut self) -> io::Result<Capabilities> {
+        self.capabilities = Capabilities::default();
+        let request = [SYSTEM_INTERFACE_SSIF];
+        let response = self.command(netfn::APP, 0, GET_SYSTEM_INTERFACE_CAPABILITIES, &request)?;
+        if response.completion_code == 0 {
+            self.capabilities = Capabilities::from_response(&response.data)?;
+        }
+
+        let pec = self.capabilities.pec_supported && self.inner.functionality()?.contains(Functionality::SMBUS_PEC);
+        self.inner.smbus_set_pec(pec)?;
+
+        Ok(self.capabilities)
+    }
+
+    /// Sends a request and waits for its response.
+    ///
+    /// Fails with `InvalidData` if the response does not match the request.
+    pub fn command(&mut self, netfn: u8, lun: u8, command: u8, data: &[u8]) -> io::Result<Response> {
+        self.write_request(netfn, lun, command, data)?;
+        let response = self.read_response()?;
+        if response.netfn != netfn | 1 || response.command != command {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "mismatched SSIF response"))
+        }
+
+        Ok(response)
+    }
+
+    /// Sends a request message, splitting it into a multi-part write if
+    /// necessary.
+    pub fn write_request(&mut self, netfn: u8, lun: u8, command: u8, data: &[u8]) -> io::Result<()> {
+        let mut message = Vec::with_capacity(2 + data.len());
+        message.push(netfn << 2 | lun & 0x03);
+        message.push(command);
+        message.extend_from_slice(data);
+
+        if message.len() > self.capabilities.max_request_len.max(BLOCK_LEN) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "SSIF request too long"))
+        }
+        if message.len() <= BLOCK_LEN {
+            return self.write_block(command::SINGLE_PART_WRITE, &message)
+        }
+
+        let parts = message.len().div_ceil(BLOCK_LEN);
+        let parts_supported = match self.capabilities.transaction_support {
+            TransactionSupport::SinglePart => 1,
+            TransactionSupport::StartEnd => 2,
+            TransactionSupport::StartMiddleEnd => usize::MAX,
+        };
+        if parts > parts_supported {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SSIF request needs unsupported multi-part write",
+            ))
+        }
+
+        for (i, part) in message.chunks(BLOCK_LEN).enumerate() {
+            let command = match i {
+                0 => command::MULTI_PART_WRITE_START,
+                i if i == parts - 1 => command::MULTI_PART_WRITE_END,
+                _ => command::MULTI_PART_WRITE_MIDDLE,
+            };
+            self.write_block(command, part)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a response message, reassembling multi-part reads.
+    ///
+    /// The BMC NACKs reads while the response is not ready, so reads are
+    /// retried until the timeout elapses.
+    pub fn read_response(&mut self) -> io::Result<Response> {
+        let mut block = [0u8; BLOCK_LEN];
+        let len = self.read_block(command::SINGLE_PART_READ, &mut block)?;
+        if block[..len].starts_with(&MULTI_PART_START) {
+            let mut message = block[MULTI_PART_START.len()..len].to_vec();
+            let mut expected = 0u8;
+            loop {
+                let len = self.read_block(command::MULTI_PART_READ_MIDDLE, &mut block)?;
+                let (&number, data) = block[..len]
+                    .split_first()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "empty SSIF read block"))?;
+                if message.len() + data.len() > MAX_RESPONSE_LEN {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "SSIF response too long"))
+                }
+                message.extend_from_slice(data);
+
+                match number {
+                    MULTI_PART_LAST_BLOCK => break,
+                    number if number == expected => expected = expected.wrapping_add(1),
+                    _ =>
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "SSIF read block out of sequence",
+                        )),
+                }
+            }
+
+            Response::from_bytes(&message)
+        } else {
+            Response::from_bytes(&block[..len])
+        }
+    }
+
+    fn write_block(&mut self, command: u8, data: &[u8]) -> io::Result<()> {
+        self.retry(|i2c| i2c.smbus_write_block_data(command, data))
+    }
+
+    fn read_block(&mut self, command: u8, data: &mut [u8]) -> io::Result<usize> {
+        self.retry(|i2c| i2c.smbus_read_block_data(command, data))
+    }
+
+    fn retry<R, F: FnMut(&mut I2c<I>) -> io::Result<R>>(&mut self, mut f: F) -> io::Result<R> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            match f(&mut self.inner) {
+                Err(ref e) if is_nack(e) && Instant::now() < deadline => sleep(self.retry_interval),
+                Err(ref e) if is_nack(e) =>
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "SSIF BMC not responding")),
+                res => return res,
+            }
+        }
+    }
+}
+
+impl<I> Ssif<I> {
+    /// The capabilities in use, as negotiated with the BMC.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Overrides the capabilities in use, such as when they are known from
+    /// platform firmware tables.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Sets how long to keep retrying a NACKed transfer.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Sets the delay between retries of a NACKed transfer.
+    pub fn set_retry_interval(&mut self, interval: Duration) {
+        self.retry_interval = interval;
+    }
+
+    /// Returns the underlying adapter.
+    pub fn into_inner(self) -> I2c<I> {
+        self.inner
+    }
+
+    /// Borrows the underlying adapter.
+    pub fn inner_ref(&self) -> &I2c<I> {
+        &self.inner
+    }
+
+    /// Mutably borrows the underlying adapter.
+    pub fn inner_mut(&mut self) -> &mut I2c<I> {
+        &mut self.inner
+    }
+}
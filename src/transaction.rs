@@ -0,0 +1,240 @@
+//! Multi-step transfers executed as a single combined transaction.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use i2c_linux::I2c;
+//!
+//! # fn main_res() -> ::std::io::Result<()> {
+//! let mut i2c = I2c::from_path("/dev/i2c-1")?;
+//! // Select page 1 of a device, then read 16 bytes from offset 0x20
+//! let reads = i2c.transaction(|tx| {
+//!     tx.write(0x50, &[0x7f, 0x01]).write(0x50, &[0x20]).read(0x50, 16);
+//! })?;
+//! println!("{:02x?}", reads[0]);
+//! # Ok(())
+//! # }
+//! # fn main() { main_res().unwrap() }
+//! ```
+
+use {
+    crate::{lock::BusLock, I2c, Message, ReadFlags, WriteFlags},
+    i2c_linux_sys as i2c,
+    std::{io, os::unix::io::AsRawFd},
+};
+
+enum Op {
+    Read {
+        address: u16,
+        len: usize,
+        flags: ReadFlags,
+    },
+    Write {
+        address: u16,
+        data: Vec<u8>,
+        flags: WriteFlags,
+    },
+}
+
+/// A queue of reads and writes, built by the closure passed to
+/// [I2c::transaction].
+#[derive(Default)]
+pub struct Transaction {
+    ops: Vec<Op>,
+}
+
+impl Transaction {
+    /// Queues a write of `data` to the device at `address`.
+    pub fn write(&mut self, address: u16, data: &[u8]) -> &mut Self {
+        self.write_with(address, data, WriteFlags::default())
+    }
+
+    /// Queues a write with additional flags.
+    pub fn write_with(&mut self, address: u16, data: &[u8], flags: WriteFlags) -> &mut Self {
+        self.ops.push(Op::Write {
+            address,
+            data: data.to_vec(),
+            flags,
+        });
+        self
+    }
+
+    /// Queues a read of `len` bytes from the device at `address`.
+    pub fn read(&mut self, address: u16, len: usize) -> &mut Self {
+        self.read_with(address, len, ReadFlags::default())
+    }
+
+    /// Queues a read with additional flags.
+    ///
+    /// With `ReadFlags::RECEIVE_LEN`, this is an SMBus block read: the first
+    /// byte received is a count of up to 32, and the read returns the data
+    /// that follows it. `len` is ignored in that case.
+    pub fn read_with(&mut self, address: u16, len: usize, flags: ReadFlags) -> &mut Self {
+        self.ops.push(Op::Read { address, len, flags });
+        self
+    }
+
+    /// The number of queued messages.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether no messages are queued.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    fn read_buffers(&self) -> Vec<Vec<u8>> {
+        self.ops
+            .iter()
+            .filter_map(|op| match *op {
+                Op::Read { flags, .. } if flags.contains(ReadFlags::RECEIVE_LEN) =>
+                    Some(vec![0u8; i2c::I2C_SMBUS_BLOCK_MAX + 1]),
+                Op::Read { len, .. } => Some(vec![0u8; len]),
+                Op::Write { .. } => None,
+            })
+            .collect()
+    }
+
+    fn execute_combined<I: AsRawFd>(&self, i2c: &mut I2c<I>) -> io::Result<Vec<Vec<u8>>> {
+        if self.ops.len() > i2c::I2C_RDWR_IOCTL_MAX_MSGS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "too many messages for a single I2C transaction",
+            ))
+        }
+
+        let mut reads = self.read_buffers();
+        let lens = {
+            let mut buffers = reads.iter_mut();
+            let mut messages = self
+                .ops
+                .iter()
+                .map(|op| match *op {
+                    Op::Read { address, flags, .. } =>
+                        read_message(address, &mut buffers.next().expect("read buffer")[..], flags),
+                    Op::Write {
+                        address,
+                        ref data,
+                        flags,
+                    } => Message::Write { address, data, flags },
+                })
+                .collect::<Vec<_>>();
+            i2c.i2c_transfer(&mut messages)?;
+
+            messages
+                .iter()
+                .filter_map(|message| match *message {
+                    Message::Read { ref data, .. } | Message::BlockRead { ref data, .. } => Some(data.len()),
+                    Message::Write { .. } => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (read, len) in reads.iter_mut().zip(lens) {
+            read.truncate(len);
+        }
+        Ok(reads)
+    }
+
+    // Splits the queue into a register write followed by a read where
+    // possible, and single messages otherwise, so that each part can be
+    // emulated with an SMBus command.
+    fn execute_sequential<I: AsRawFd>(&self, i2c: &mut I2c<I>) -> io::Result<Vec<Vec<u8>>> {
+        let mut reads = self.read_buffers();
+        let mut buffers = reads.iter_mut();
+        let mut ops = self.ops.iter().peekable();
+        while let Some(op) = ops.next() {
+            match *op {
+                Op::Write {
+                    address,
+                    ref data,
+                    flags,
+                } => match ops.peek() {
+                    Some(&&Op::Read {
+                        address: read_address,
+                        flags: read_flags,
+                        ..
+                    }) if read_address == address => {
+                        ops.next();
+                        let buffer = buffers.next().expect("read buffer");
+                        let mut messages = [
+                            Message::Write { address, data, flags },
+                            read_message(address, &mut buffer[..], read_flags),
+                        ];
+                        i2c.i2c_transfer(&mut messages)?;
+                        let len = messages[1].len();
+                        buffer.truncate(len);
+                    },
+                    _ => i2c.i2c_transfer(&mut [Message::Write { address, data, flags }])?,
+                },
+                Op::Read { address, flags, .. } => {
+                    let buffer = buffers.next().expect("read buffer");
+                    let mut messages = [read_message(address, &mut buffer[..], flags)];
+                    i2c.i2c_transfer(&mut messages)?;
+                    let len = messages[0].len();
+                    buffer.truncate(len);
+                },
+            }
+        }
+
+        Ok(reads)
+    }
+}
+
+// Raw RECEIVE_LEN reads need their initial length written into the buffer up
+// front, so SMBus block reads are queued as `Message::BlockRead` instead.
+fn read_message(address: u16, data: &mut [u8], flags: ReadFlags) -> Message<'_> {
+    if flags.contains(ReadFlags::RECEIVE_LEN) {
+        Message::BlockRead {
+            address,
+            data,
+            flags: flags - ReadFlags::RECEIVE_LEN,
+            pec: false,
+        }
+    } else {
+        Message::Read { address, data, flags }
+    }
+}
+
+impl<I: AsRawFd> I2c<I> {
+    /// Queues reads and writes with `f`, then executes them as a single
+    /// combined `I2C_RDWR` transfer that other bus users cannot interrupt.
+    ///
+    /// Returns the data of each read in the order they were queued,
+    /// truncated to the length actually read.
+    ///
+    /// Adapters without `Functionality::I2C` cannot combine transfers, so
+    /// the queue is instead split into SMBus commands, each ending with a
+    /// STOP: a write followed by a read from the same device becomes one
+    /// command, and any other message becomes its own. See
+    /// [i2c_transfer](Self::i2c_transfer) for the messages this supports.
+    /// The commands are run while holding [BusLock::device] to keep out
+    /// cooperating processes; use
+    /// [transaction_with_lock](Self::transaction_with_lock) to pick another
+    /// lock. Calling this from within [with_lock](Self::with_lock) joins the
+    /// lock already held rather than releasing it early.
+    pub fn transaction<F: FnOnce(&mut Transaction)>(&mut self, f: F) -> io::Result<Vec<Vec<u8>>> {
+        self.transaction_with_lock(&BusLock::device(), f)
+    }
+
+    /// Like [transaction](Self::transaction), but holds `lock` while running
+    /// the SMBus commands that replace a combined transfer.
+    pub fn transaction_with_lock<F: FnOnce(&mut Transaction)>(
+        &mut self,
+        lock: &BusLock,
+        f: F,
+    ) -> io::Result<Vec<Vec<u8>>> {
+        let mut tx = Transaction::default();
+        f(&mut tx);
+        if tx.is_empty() {
+            return Ok(Vec::new())
+        }
+
+        if self.supports_i2c_transfer()? {
+            tx.execute_combined(self)
+        } else {
+            self.with_lock(lock, |i2c| tx.execute_sequential(i2c))
+        }
+    }
+}